@@ -0,0 +1,265 @@
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+
+const BLANK: &str = "___";
+// Cards are identified by their position in the deck as a u16
+const MAX_DECK_SIZE: usize = u16::MAX as usize + 1;
+
+/// A deck of prompts or finishers, as read from the files in `PROMPTS_PATH` and `FINISHERS_PATH`.
+///
+/// Files ending in `.toml` carry a pack header and metadata for every card:
+///
+/// ```toml
+/// [pack]
+/// name = "Chapín"
+/// version = "1.0"
+/// locale = "es-GT"
+///
+/// [[cards]]
+/// text = "Lo que más extraño de Guate es ___."
+/// blanks = 1
+/// nsfw = false
+/// author = "cavpollo"
+/// ```
+///
+/// Any other file is read as plain text, one card per line, with empty lines skipped.
+pub struct Deck {
+    pub pack: DeckPack,
+    pub cards: Vec<Card>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeckPack {
+    pub name: String,
+    pub version: Option<String>,
+    pub locale: Option<String>
+}
+
+pub struct Card {
+    pub text: String,
+    pub pack: String, // The one in the header, unless the card says otherwise
+    pub language: Option<String>, // The locale in the header, unless the card says otherwise
+    pub is_nsfw: bool,
+    pub author: Option<String>,
+    pub blanks: u8
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeckKind {
+    Prompts,
+    Finishers
+}
+
+#[derive(Debug)]
+pub struct DeckError {
+    pub line: Option<usize>,
+    pub message: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeckFile {
+    pack: DeckPack,
+    cards: Vec<Spanned<CardEntry>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CardEntry {
+    text: String,
+    pack: Option<String>,
+    language: Option<String>,
+    #[serde(default)]
+    nsfw: bool,
+    author: Option<String>,
+    blanks: Option<u8>
+}
+
+impl Deck {
+    pub fn load(path: &str, deck_kind: DeckKind) -> Result<Deck, DeckError> {
+        let content = fs::read_to_string(path).map_err(|error| DeckError { line: None, message: error.to_string() })?;
+
+        Deck::parse(&content, path.ends_with(".toml"), deck_kind)
+    }
+
+    pub fn texts(&self) -> Vec<String> {
+        self.cards.iter().map(|card| card.text.clone()).collect()
+    }
+
+    fn parse(content: &str, is_toml: bool, deck_kind: DeckKind) -> Result<Deck, DeckError> {
+        let deck = if is_toml {
+            Deck::from_toml(content, deck_kind)?
+        } else {
+            Deck::from_lines(content, deck_kind)?
+        };
+
+        if deck.cards.is_empty() {
+            return Err(DeckError { line: None, message: "The deck has no cards".to_string() });
+        }
+
+        if deck.cards.len() > MAX_DECK_SIZE {
+            return Err(DeckError { line: None, message: format!("The deck has {} cards, but at most {} are allowed", deck.cards.len(), MAX_DECK_SIZE) });
+        }
+
+        Ok(deck)
+    }
+
+    fn from_lines(content: &str, deck_kind: DeckKind) -> Result<Deck, DeckError> {
+        let pack = DeckPack { name: "Default".to_string(), version: None, locale: None };
+
+        let mut cards = vec![];
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let blanks = validate_card(line, None, deck_kind).map_err(|message| DeckError { line: Some(i + 1), message })?;
+            cards.push(Card {
+                text: line.to_string(),
+                pack: pack.name.clone(),
+                language: None,
+                is_nsfw: false,
+                author: None,
+                blanks
+            });
+        }
+
+        Ok(Deck { pack, cards })
+    }
+
+    fn from_toml(content: &str, deck_kind: DeckKind) -> Result<Deck, DeckError> {
+        let deck_file: DeckFile = toml::from_str(content).map_err(|error| DeckError {
+            line: error.span().map(|span| line_number(content, span)),
+            message: error.message().to_string()
+        })?;
+
+        if deck_file.pack.name.trim().is_empty() {
+            return Err(DeckError { line: None, message: "The pack has no name".to_string() });
+        }
+
+        let mut cards = vec![];
+        for spanned_card_entry in deck_file.cards {
+            let line = line_number(content, spanned_card_entry.span());
+            let card_entry = spanned_card_entry.into_inner();
+
+            let blanks = validate_card(&card_entry.text, card_entry.blanks, deck_kind).map_err(|message| DeckError { line: Some(line), message })?;
+            cards.push(Card {
+                text: card_entry.text,
+                pack: card_entry.pack.unwrap_or_else(|| deck_file.pack.name.clone()),
+                language: card_entry.language.or_else(|| deck_file.pack.locale.clone()),
+                is_nsfw: card_entry.nsfw,
+                author: card_entry.author,
+                blanks
+            });
+        }
+
+        Ok(Deck { pack: deck_file.pack, cards })
+    }
+}
+
+impl fmt::Display for DeckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+
+// Returns how many blanks the card has
+fn validate_card(text: &str, blanks: Option<u8>, deck_kind: DeckKind) -> Result<u8, String> {
+    if text.trim().is_empty() {
+        return Err("The card has no text".to_string());
+    }
+
+    let text_blanks = text.matches(BLANK).count();
+    if deck_kind == DeckKind::Finishers && text_blanks > 0 {
+        return Err(format!("Finishers cant have blanks ({})", BLANK));
+    }
+
+    let text_blanks = u8::try_from(text_blanks).map_err(|_| "The card has too many blanks".to_string())?;
+    match blanks {
+        Some(blanks) if blanks != text_blanks => Err(format!("The card says it has {} blanks, but its text has {}", blanks, text_blanks)),
+        _ => Ok(text_blanks)
+    }
+}
+
+fn line_number(content: &str, span: Range<usize>) -> usize {
+    content[..span.start.min(content.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROMPTS_TOML: &str = r#"[pack]
+name = "Chapín"
+locale = "es-GT"
+
+[[cards]]
+text = "Lo que más extraño es ___."
+blanks = 1
+
+[[cards]]
+text = "___ y ___."
+language = "es-MX"
+nsfw = true
+author = "cavpollo"
+"#;
+
+    fn error_line(result: Result<Deck, DeckError>) -> Option<usize> {
+        match result {
+            Ok(_) => panic!("The deck should not be valid"),
+            Err(error) => error.line
+        }
+    }
+
+    #[test]
+    fn reads_plain_text_skipping_empty_lines() {
+        let deck = Deck::parse("Uno ___\n\n  \nDos ___ y ___\n", false, DeckKind::Prompts).unwrap();
+        assert_eq!(deck.texts(), vec!["Uno ___", "Dos ___ y ___"]);
+        assert_eq!(deck.cards[1].blanks, 2);
+
+        assert_eq!(error_line(Deck::parse("Uno\n\nDos ___\n", false, DeckKind::Finishers)), Some(3));
+    }
+
+    #[test]
+    fn reads_toml_with_metadata() {
+        let deck = Deck::parse(PROMPTS_TOML, true, DeckKind::Prompts).unwrap();
+        assert_eq!(deck.pack.name, "Chapín");
+        assert_eq!(deck.cards.len(), 2);
+
+        assert_eq!(deck.cards[0].pack, "Chapín");
+        assert_eq!(deck.cards[0].language.as_deref(), Some("es-GT"));
+        assert!(!deck.cards[0].is_nsfw);
+
+        assert_eq!(deck.cards[1].language.as_deref(), Some("es-MX"));
+        assert_eq!(deck.cards[1].author.as_deref(), Some("cavpollo"));
+        assert_eq!(deck.cards[1].blanks, 2);
+        assert!(deck.cards[1].is_nsfw);
+    }
+
+    #[test]
+    fn reports_the_line_of_invalid_toml_cards() {
+        assert_eq!(error_line(Deck::parse(&PROMPTS_TOML.replace("blanks = 1", "blanks = 2"), true, DeckKind::Prompts)), Some(5));
+        assert_eq!(error_line(Deck::parse(&PROMPTS_TOML.replace("author", "autor"), true, DeckKind::Prompts)), Some(13));
+        assert_eq!(error_line(Deck::parse(&PROMPTS_TOML.replace("\"___ y ___.\"", "\"___ y ___."), true, DeckKind::Prompts)), Some(10));
+        assert_eq!(error_line(Deck::parse(&PROMPTS_TOML.replace("name = \"Chapín\"", "name = \" \""), true, DeckKind::Prompts)), None);
+    }
+
+    #[test]
+    fn rejects_decks_too_big_for_the_card_ids() {
+        let cards = "Carta\n".repeat(MAX_DECK_SIZE);
+        assert_eq!(Deck::parse(&cards, false, DeckKind::Finishers).unwrap().cards.len(), MAX_DECK_SIZE);
+
+        let too_many_cards = "Carta\n".repeat(MAX_DECK_SIZE + 1);
+        assert!(Deck::parse(&too_many_cards, false, DeckKind::Finishers).is_err());
+        assert!(Deck::parse("\n", false, DeckKind::Finishers).is_err());
+    }
+}
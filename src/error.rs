@@ -0,0 +1,113 @@
+use std::fmt;
+
+use crate::game::RoomStatus;
+use crate::protocol::ResponseError;
+
+
+/// Every way a request can fail, from unreadable HTTP bodies to game rule violations.
+///
+/// The `code` of each variant is part of the client contract (it gets localized on their side),
+/// so never rename an existing one.
+#[derive(Debug, PartialEq)]
+pub enum GameError {
+    UnknownAction,
+    BadHeaders,
+    UnreadableContent,
+    InvalidJson,
+    InvalidData,
+    NotAuthenticated,
+    RoomNotFound(u32),
+    RoomCodeNotFound(String),
+    RoomLocked,
+    RoomFull(u8),
+    PlayerBanned,
+    PlayerNameEmpty,
+    PlayerNameTooLong(u8),
+    PlayerNameInvalidCharacters,
+    BlockedWord,
+    PlayerNameTaken(String),
+    PlayerNotInRoom(u32, u32), // PlayerId, RoomId
+    NotOwner(u32),
+    NotEnoughPlayers(u8),
+    WrongRoomStatus(RoomStatus),
+    OptionNotFound(u16),
+    FinisherAlreadySubmitted,
+    NoFinishersSubmitted,
+    TooEarly(u64) // Seconds left
+}
+
+impl GameError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            GameError::UnknownAction => "UNKNOWN_ACTION",
+            GameError::BadHeaders => "BAD_HEADERS",
+            GameError::UnreadableContent => "UNREADABLE_CONTENT",
+            GameError::InvalidJson => "INVALID_JSON",
+            GameError::InvalidData => "INVALID_DATA",
+            GameError::NotAuthenticated => "NOT_AUTHENTICATED",
+            GameError::RoomNotFound(..) | GameError::RoomCodeNotFound(..) => "ROOM_NOT_FOUND",
+            GameError::RoomLocked => "ROOM_LOCKED",
+            GameError::RoomFull(..) => "ROOM_FULL",
+            GameError::PlayerBanned => "PLAYER_BANNED",
+            GameError::PlayerNameEmpty => "PLAYER_NAME_EMPTY",
+            GameError::PlayerNameTooLong(..) => "PLAYER_NAME_TOO_LONG",
+            GameError::PlayerNameInvalidCharacters => "PLAYER_NAME_INVALID_CHARACTERS",
+            GameError::BlockedWord => "BLOCKED_WORD",
+            GameError::PlayerNameTaken(..) => "PLAYER_NAME_TAKEN",
+            GameError::PlayerNotInRoom(..) => "PLAYER_NOT_IN_ROOM",
+            GameError::NotOwner(..) => "NOT_OWNER",
+            GameError::NotEnoughPlayers(..) => "NOT_ENOUGH_PLAYERS",
+            GameError::WrongRoomStatus(..) => "WRONG_ROOM_STATUS",
+            GameError::OptionNotFound(..) => "OPTION_NOT_FOUND",
+            GameError::FinisherAlreadySubmitted => "FINISHER_ALREADY_SUBMITTED",
+            GameError::NoFinishersSubmitted => "NO_FINISHERS_SUBMITTED",
+            GameError::TooEarly(..) => "TOO_EARLY"
+        }
+    }
+
+    pub fn http_status(&self) -> u16 {
+        match self {
+            GameError::UnknownAction | GameError::RoomNotFound(..) | GameError::RoomCodeNotFound(..) => 404,
+            GameError::NotAuthenticated => 401,
+            _ => 400
+        }
+    }
+
+    pub fn to_response(&self) -> ResponseError {
+        ResponseError {
+            error: self.code().to_string(),
+            message: self.to_string()
+        }
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::UnknownAction => write!(f, "Unknown action"),
+            GameError::BadHeaders => write!(f, "Bad headers"),
+            GameError::UnreadableContent => write!(f, "Cant read request content"),
+            GameError::InvalidJson => write!(f, "Cant read JSON"),
+            GameError::InvalidData => write!(f, "Invalid data"),
+            GameError::NotAuthenticated => write!(f, "Not authenticated"),
+            GameError::RoomNotFound(room_id) => write!(f, "Room {} not found", room_id),
+            GameError::RoomCodeNotFound(room_code) => write!(f, "Room '{}' not found", room_code),
+            GameError::RoomLocked => write!(f, "Room is locked"),
+            GameError::RoomFull(max_players) => write!(f, "Room is full ({} players)", max_players),
+            GameError::PlayerBanned => write!(f, "Player is banned from the room"),
+            GameError::PlayerNameEmpty => write!(f, "Player name is empty"),
+            GameError::PlayerNameTooLong(max_length) => write!(f, "Player name is longer than {} characters", max_length),
+            GameError::PlayerNameInvalidCharacters => write!(f, "Player name has invalid characters"),
+            GameError::BlockedWord => write!(f, "Text has a blocked word"),
+            GameError::PlayerNameTaken(player_name) => write!(f, "There is already a player named '{}' in the room", player_name),
+            GameError::PlayerNotInRoom(player_id, room_id) => write!(f, "Player {} not found in room {}", player_id, room_id),
+            GameError::NotOwner(player_id) => write!(f, "Player {} is not the owner of the room", player_id),
+            GameError::NotEnoughPlayers(player_count) => write!(f, "Not enough players in room ({})", player_count),
+            GameError::WrongRoomStatus(room_status) => write!(f, "Action not allowed on room status {}", room_status),
+            GameError::OptionNotFound(option_id) => write!(f, "Option {} not found", option_id),
+            GameError::FinisherAlreadySubmitted => write!(f, "Finisher already submitted"),
+            GameError::NoFinishersSubmitted => write!(f, "No finishers submitted yet"),
+            GameError::TooEarly(seconds_left) => write!(f, "Too early, try again in {} seconds", seconds_left)
+        }
+    }
+}
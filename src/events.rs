@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+
+use serde_json::{Map, Value};
+
+use crate::protocol::ResponseRoomCheck;
+
+
+// Events a subscriber can fall behind on before it is dropped as gone
+const SUBSCRIBER_QUEUE_SIZE: usize = 32;
+
+/// Server-Sent Events subscribers, grouped by room.
+///
+/// A new subscriber receives the whole room state as a `room` event. After that, every published
+/// state is compared against the previous one and only the changed top-level fields are pushed as
+/// a `delta` event, so clients can merge them into the last state they know about.
+///
+/// Each subscriber gets its own thread to write to, so a client that stops reading only holds back itself.
+pub struct RoomEvents {
+    subscribers: HashMap<u32, Vec<Subscriber>>,
+    last_states: HashMap<u32, Value>
+}
+
+struct Subscriber {
+    player_id: u32,
+    sender: SyncSender<String>
+}
+
+impl Default for RoomEvents {
+    fn default() -> Self {
+        RoomEvents::new()
+    }
+}
+
+impl RoomEvents {
+    pub fn new() -> RoomEvents {
+        RoomEvents {
+            subscribers: HashMap::new(),
+            last_states: HashMap::new()
+        }
+    }
+
+    pub fn has_subscribers(&self, room_id: u32) -> bool {
+        self.subscribers.get(&room_id).is_some_and(|room_subscribers| !room_subscribers.is_empty())
+    }
+
+    /// The room and player of every open stream
+    pub fn sessions(&self) -> Vec<(u32, u32)> {
+        self.subscribers.iter()
+            .flat_map(|(&room_id, room_subscribers)| room_subscribers.iter().map(move |subscriber| (room_id, subscriber.player_id)))
+            .collect()
+    }
+
+    pub fn subscribe(&mut self, room_id: u32, player_id: u32, writer: Box<dyn Write + Send>, room_state: &ResponseRoomCheck) {
+        // Bring the existing subscribers up to date first, so that every one of them shares the same last state
+        self.publish(room_id, room_state);
+
+        let state = serde_json::to_value(room_state).unwrap();
+        let sender = spawn_writer(writer);
+        // Cant be full yet
+        let _ = sender.try_send(event_frame("room", &state));
+
+        self.last_states.insert(room_id, state);
+        self.subscribers.entry(room_id).or_default().push(Subscriber { player_id, sender });
+    }
+
+    pub fn publish(&mut self, room_id: u32, room_state: &ResponseRoomCheck) {
+        let state = serde_json::to_value(room_state).unwrap();
+
+        let delta = match (self.last_states.get(&room_id), &state) {
+            (Some(Value::Object(last_fields)), Value::Object(fields)) => {
+                let changed_fields: Map<String, Value> = fields.iter()
+                    .filter(|&(key, value)| last_fields.get(key) != Some(value))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                Value::Object(changed_fields)
+            },
+            _ => state.clone()
+        };
+        self.last_states.insert(room_id, state);
+
+        if delta.as_object().is_some_and(|changed_fields| changed_fields.is_empty()) {
+            return;
+        }
+
+        if let Some(room_subscribers) = self.subscribers.get_mut(&room_id) {
+            // Closed connections are noticed once their writer gives up, slow ones once their queue is full
+            let frame = event_frame("delta", &delta);
+            room_subscribers.retain(|subscriber| subscriber.sender.try_send(frame.clone()).is_ok());
+        }
+    }
+
+    /// Ends the streams of a player that is no longer in the room
+    pub fn close_player(&mut self, room_id: u32, player_id: u32) {
+        if let Some(room_subscribers) = self.subscribers.get_mut(&room_id) {
+            room_subscribers.retain(|subscriber| subscriber.player_id != player_id);
+        }
+    }
+
+    /// Ends the streams of a room that no longer exists
+    pub fn close_room(&mut self, room_id: u32) {
+        self.subscribers.remove(&room_id);
+        self.last_states.remove(&room_id);
+    }
+
+    pub fn keep_alive(&mut self) {
+        for room_subscribers in self.subscribers.values_mut() {
+            room_subscribers.retain(|subscriber| subscriber.sender.try_send(": keep-alive\n\n".to_string()).is_ok());
+        }
+
+        self.subscribers.retain(|_, room_subscribers| !room_subscribers.is_empty());
+        let subscribers = &self.subscribers;
+        self.last_states.retain(|room_id, _| subscribers.contains_key(room_id));
+    }
+}
+
+fn event_frame(event: &str, data: &Value) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+// The thread ends along with the connection, or once the subscriber is dropped.
+// Frames go out as chunks, since an empty one is the only way to end the stream while the connection is kept alive.
+fn spawn_writer(mut writer: Box<dyn Write + Send>) -> SyncSender<String> {
+    let (sender, receiver) = mpsc::sync_channel::<String>(SUBSCRIBER_QUEUE_SIZE);
+
+    thread::spawn(move || {
+        for frame in receiver {
+            if write!(writer, "{:x}\r\n{}\r\n", frame.len(), frame).and_then(|_| writer.flush()).is_err() {
+                return;
+            }
+        }
+        let _ = writer.write_all(b"0\r\n\r\n").and_then(|_| writer.flush());
+    });
+
+    sender
+}
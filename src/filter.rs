@@ -0,0 +1,148 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::error::GameError;
+use crate::names::name_key;
+
+
+/// What to do with text that has a blocked word in it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterAction {
+    Reject,
+    Mask // Every character of the word becomes a '*'
+}
+
+/// A filter for the text players type, based on a locally configured list of blocked words.
+///
+/// Words are compared ignoring case and accents, with the usual leetspeak digits and symbols read as
+/// letters and letters repeated at least as many times as in the blocked word, so "PÜÜT0" is caught by
+/// "puto" but "as" is not caught by "ass". Only whole words are compared, so "computadora" is not
+/// caught by "puta".
+pub struct WordFilter {
+    blocked_words: Vec<Vec<(char, usize)>>,
+    filter_action: FilterAction
+}
+
+impl WordFilter {
+    pub fn new(blocked_words: &[String], filter_action: FilterAction) -> WordFilter {
+        WordFilter {
+            blocked_words: blocked_words.iter()
+                .map(|blocked_word| letter_runs(&blocked_word.graphemes(true).map(fold).collect::<String>()))
+                .filter(|blocked_word| !blocked_word.is_empty())
+                .collect(),
+            filter_action
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> Result<String, GameError> {
+        let characters: Vec<(&str, String)> = text.graphemes(true).map(|character| (character, fold(character))).collect();
+
+        // Start and end of every word, in characters
+        let mut words = vec![];
+        let mut word_start = None;
+        for (i, (_, folded_character)) in characters.iter().enumerate() {
+            let is_word_character = folded_character.chars().all(char::is_alphanumeric);
+            match word_start {
+                None if is_word_character => word_start = Some(i),
+                Some(start) if !is_word_character => {
+                    words.push((start, i));
+                    word_start = None;
+                },
+                _ => {}
+            }
+        }
+        if let Some(start) = word_start {
+            words.push((start, characters.len()));
+        }
+
+        let blocked_words: Vec<(usize, usize)> = words.into_iter()
+            .filter(|&(start, end)| {
+                let word_runs = letter_runs(&characters[start..end].iter().map(|(_, folded_character)| folded_character.as_str()).collect::<String>());
+                self.blocked_words.iter().any(|blocked_word| is_stretched(&word_runs, blocked_word))
+            })
+            .collect();
+
+        if blocked_words.is_empty() {
+            return Ok(text.to_string());
+        }
+
+        match self.filter_action {
+            FilterAction::Reject => Err(GameError::BlockedWord),
+            FilterAction::Mask => Ok(characters.iter().enumerate()
+                .map(|(i, (character, _))| if blocked_words.iter().any(|&(start, end)| (start..end).contains(&i)) { "*" } else { character })
+                .collect())
+        }
+    }
+}
+
+// Without case, accents or leetspeak
+fn fold(character: &str) -> String {
+    match name_key(character).as_str() {
+        "0" => "o".to_string(),
+        "1" => "i".to_string(),
+        "3" => "e".to_string(),
+        "4" | "@" => "a".to_string(),
+        "5" | "$" => "s".to_string(),
+        "7" => "t".to_string(),
+        "8" => "b".to_string(),
+        "9" => "g".to_string(),
+        folded_character => folded_character.to_string()
+    }
+}
+
+// Every letter with how many times it is repeated in a row
+fn letter_runs(word: &str) -> Vec<(char, usize)> {
+    let mut runs: Vec<(char, usize)> = vec![];
+    for c in word.chars() {
+        match runs.last_mut() {
+            Some((last_c, count)) if *last_c == c => *count += 1,
+            _ => runs.push((c, 1))
+        }
+    }
+    runs
+}
+
+// The same letters as the blocked word, each one repeated at least as many times
+fn is_stretched(word_runs: &[(char, usize)], blocked_word_runs: &[(char, usize)]) -> bool {
+    word_runs.len() == blocked_word_runs.len()
+        && word_runs.iter().zip(blocked_word_runs).all(|(&(c, count), &(blocked_c, blocked_count))| c == blocked_c && count >= blocked_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word_filter(filter_action: FilterAction) -> WordFilter {
+        WordFilter::new(&["ass".to_string(), "puto".to_string(), "pendejo".to_string()], filter_action)
+    }
+
+    #[test]
+    fn keeps_words_with_fewer_repeated_letters() {
+        let word_filter = word_filter(FilterAction::Mask);
+        assert_eq!(word_filter.apply("As de Oros"), Ok("As de Oros".to_string()));
+        assert_eq!(word_filter.apply("Pasa el as"), Ok("Pasa el as".to_string()));
+        assert_eq!(word_filter.apply("Mi asss"), Ok("Mi ****".to_string()));
+        assert_eq!(word_filter.apply("puuuuto"), Ok("*******".to_string()));
+    }
+
+    #[test]
+    fn reads_leetspeak_as_letters() {
+        let word_filter = word_filter(FilterAction::Reject);
+        assert_eq!(word_filter.apply("p3nd3j0"), Err(GameError::BlockedWord));
+        assert_eq!(word_filter.apply("@$$"), Err(GameError::BlockedWord));
+        assert_eq!(word_filter.apply("7 puntos"), Ok("7 puntos".to_string()));
+    }
+
+    #[test]
+    fn ignores_case_and_accents() {
+        let word_filter = word_filter(FilterAction::Mask);
+        assert_eq!(word_filter.apply("PÜÜT0 Juan"), Ok("***** Juan".to_string()));
+        assert_eq!(word_filter.apply("el PÉNDEJO!"), Ok("el *******!".to_string()));
+    }
+
+    #[test]
+    fn compares_whole_words_only() {
+        let word_filter = word_filter(FilterAction::Reject);
+        assert_eq!(word_filter.apply("computadora"), Ok("computadora".to_string()));
+        assert_eq!(word_filter.apply("Clase de masaje"), Ok("Clase de masaje".to_string()));
+    }
+}
@@ -0,0 +1,1940 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use rand::distributions::{Alphanumeric, DistString};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::error::GameError;
+use crate::filter::WordFilter;
+use crate::names::{name_key, sanitize_player_name, suffixed_player_name};
+use crate::persistence::{RoomStore, instant_age};
+use crate::protocol::{
+    GameMode, PhaseTimers, ResponseGameAction, ResponseGameOptions, ResponseGameOptionsOption, ResponseRoomCheck, ResponseRoomCheckFinisher,
+    ResponseRoomCheckPlayer, ResponseRoomCheckRound, ResponseRoomCheckStanding, ResponseRoomCreate, ResponseRoomHistory,
+    ResponseRoomHistoryFinisher, ResponseRoomHistoryRound, ResponseRoomJoin, RoomSettings, RoomSettingsUpdate, TieBreak
+};
+
+
+const ROOM_CODE_LENGTH: usize = 6;
+// Server-wide bounds for the room settings
+const MAX_ROUND_TOTAL: u8 = 50;
+const MAX_HAND_SIZE: u8 = 12;
+const MAX_PROMPT_OPTION_COUNT: u8 = 5;
+const MIN_PLAYERS: u8 = 3; // A leader and at least two lackeys, so that there is something to pick from
+const MAX_PLAYERS: u8 = 100;
+const MAX_SCORE_TO_WIN: u8 = 50;
+const MAX_PHASE_SECONDS: u16 = 600;
+// How long the leader has to wait for the lackeys before being able to force the game along
+const LEADER_FORCE_ADVANCE_WAIT: Duration = Duration::from_secs(60);
+// Without vowels no word (offensive or not) can show up, which also leaves out O and I, and 0, 1, 3 and 4 that
+// read as vowels. Without 2, 5, 6 and 8 there is nothing left to confuse with Z, S, G and B.
+const ROOM_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ79";
+
+
+/// The whole game state machine, without any knowledge of HTTP.
+///
+/// Every action validates its input against the current state and either applies the
+/// transition and returns its result, or returns a `GameError` leaving the state untouched.
+///
+/// The engine can be shared between threads: each room sits behind its own lock, so actions
+/// on different rooms never wait for each other, while actions on the same room are serialized.
+pub struct GameEngine {
+    prompts: Vec<String>,
+    finishers: Vec<String>,
+    duplicate_names: DuplicateNames,
+    word_filter: Option<WordFilter>,
+    rooms: RwLock<Rooms>
+}
+
+struct Rooms {
+    by_id: HashMap<u32, Arc<Mutex<Room>>>,
+    ids_by_code: HashMap<String, u32>
+}
+
+/// What to do when someone joins a room with the name of a player already in it.
+/// Names are compared ignoring case and accents, so "Juan" and "JUÁN" are the same player to everyone else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DuplicateNames {
+    Reject,
+    Suffix // "Juan (2)"
+}
+
+impl GameEngine {
+    pub fn new(prompts: Vec<String>, finishers: Vec<String>) -> GameEngine {
+        GameEngine {
+            prompts,
+            finishers,
+            duplicate_names: DuplicateNames::Reject,
+            word_filter: None,
+            rooms: RwLock::new(Rooms {
+                by_id: HashMap::new(),
+                ids_by_code: HashMap::new()
+            })
+        }
+    }
+
+    pub fn with_duplicate_names(mut self, duplicate_names: DuplicateNames) -> GameEngine {
+        self.duplicate_names = duplicate_names;
+        self
+    }
+
+    pub fn with_word_filter(mut self, word_filter: WordFilter) -> GameEngine {
+        self.word_filter = Some(word_filter);
+        self
+    }
+
+    // Everything players type goes through here before anyone else gets to see it
+    fn filter_text(&self, text: &str) -> Result<String, GameError> {
+        match &self.word_filter {
+            Some(word_filter) => word_filter.apply(text),
+            None => Ok(text.to_string())
+        }
+    }
+
+    pub fn create_room(&self, owner_name: &str, settings: Option<RoomSettings>) -> Result<ResponseRoomCreate, GameError> {
+        let owner_name = self.filter_text(&sanitize_player_name(owner_name)?)?;
+
+        let settings = settings.unwrap_or_default();
+        validate_settings(&settings)?;
+
+        let mut rng = rand::thread_rng();
+
+        // Holding the lock from generating the id and code until the room is inserted, so no one else can take them
+        let mut rooms = self.rooms.write().unwrap();
+        let room_id = rooms.unused_id(&mut rng);
+        let room_code = rooms.unused_code(&mut rng);
+        let player_id = random_id(&mut rng);
+
+        let owner = Player::new(player_id, &owner_name);
+        let player_token = owner.token.clone();
+        let reconnect_token = owner.reconnect_token.clone();
+
+        let room = Room {
+            id: room_id,
+            code: room_code.clone(),
+            room_status: RoomStatus::Waiting,
+            status_changed_at: Instant::now(),
+            settings,
+            owner_id: player_id,
+            round_counter: 1,
+            leader_player_position: 0,
+            selected_prompt_id: None,
+            winner_player_id: None,
+            winner_finisher_id: None,
+            players: vec![owner],
+            prompt_options: vec![],
+            available_prompts: vec![],
+            available_finishers: vec![],
+            is_locked: false,
+            banned_names: vec![],
+            game_counter: 1,
+            rounds: vec![]
+        };
+
+        rooms.by_id.insert(room_id, Arc::new(Mutex::new(room)));
+        rooms.ids_by_code.insert(room_code.clone(), room_id);
+
+        Ok(ResponseRoomCreate { room_id, room_code, player_id, player_token, reconnect_token })
+    }
+
+    pub fn join_room(&self, room_code: &str, player_name: &str) -> Result<ResponseRoomJoin, GameError> {
+        let sanitized_player_name = self.filter_text(&sanitize_player_name(player_name)?)?;
+        let room_id = self.room_id_by_code(room_code)?;
+
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+
+        if room.is_locked {
+            return Err(GameError::RoomLocked);
+        }
+
+        if room.banned_names.contains(&name_key(&sanitized_player_name)) {
+            return Err(GameError::PlayerBanned);
+        }
+
+        if room.players.len() >= usize::from(room.settings.max_players) {
+            return Err(GameError::RoomFull(room.settings.max_players));
+        }
+
+        let player_name = match self.duplicate_names {
+            _ if !room.has_player_named(&sanitized_player_name) => sanitized_player_name,
+            DuplicateNames::Reject => return Err(GameError::PlayerNameTaken(sanitized_player_name)),
+            DuplicateNames::Suffix => (2..)
+                .map(|n| suffixed_player_name(&sanitized_player_name, n))
+                .find(|suffixed_player_name| !room.has_player_named(suffixed_player_name))
+                .unwrap()
+        };
+
+        let player_id = room.unused_player_id(&mut rand::thread_rng());
+        let player = Player::new(player_id, &player_name);
+        let player_token = player.token.clone();
+        let reconnect_token = player.reconnect_token.clone();
+        room.players.push(player);
+
+        Ok(ResponseRoomJoin { room_id, player_id, player_name, player_token, reconnect_token })
+    }
+
+    /// Gives back a player that lost their session, with their score, hand and submission, in exchange for the
+    /// reconnect token issued when they created or joined the room. The old player token stops working, but the
+    /// streams and sockets already authenticated with it are up to the caller to close.
+    pub fn rejoin_room(&self, room_code: &str, reconnect_token: &str) -> Result<ResponseRoomJoin, GameError> {
+        if reconnect_token.is_empty() {
+            return Err(GameError::InvalidData);
+        }
+        let room_id = self.room_id_by_code(room_code)?;
+
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+
+        // Locks, bans and the player limit do not apply, they never left the room
+        let player = match room.players.iter_mut().find(|p| p.reconnect_token == reconnect_token) {
+            Some(player) => player,
+            None => return Err(GameError::NotAuthenticated)
+        };
+        player.token = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+        player.last_check = Instant::now();
+        player.is_connected = true;
+
+        Ok(ResponseRoomJoin {
+            room_id,
+            player_id: player.id,
+            player_name: player.name.clone(),
+            player_token: player.token.clone(),
+            reconnect_token: player.reconnect_token.clone()
+        })
+    }
+
+    /// Makes sure the token is the one issued to the player when creating or joining the room
+    pub fn authenticate(&self, room_id: u32, player_id: u32, player_token: &str) -> Result<(), GameError> {
+        let room = self.room(room_id)?;
+        let room = room.lock().unwrap();
+
+        if room.player(player_id)?.token != player_token {
+            return Err(GameError::NotAuthenticated);
+        }
+
+        Ok(())
+    }
+
+    pub fn check(&self, room_id: u32, player_id: u32) -> Result<ResponseRoomCheck, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+
+        let player = room.player_mut(player_id)?;
+        player.last_check = Instant::now();
+
+        Ok(self.room_check_response(&room))
+    }
+
+    /// The same state returned by `check`, but without it counting as a check from any player
+    pub fn room_state(&self, room_id: u32) -> Result<ResponseRoomCheck, GameError> {
+        let room = self.room(room_id)?;
+        let room = room.lock().unwrap();
+
+        Ok(self.room_check_response(&room))
+    }
+
+    pub fn start(&self, room_id: u32, player_id: u32) -> Result<ResponseGameAction, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        match room.room_status {
+            RoomStatus::Waiting => {
+                if player_id != room.owner_id {
+                    return Err(GameError::NotOwner(player_id));
+                }
+                room.check_enough_players()?;
+
+                // New game and round
+                room.set_status(RoomStatus::LeaderOptions);
+
+                // No need to set the round_counter or leader_player_position.
+                // The default values with which the Room was created are fine.
+            },
+            RoomStatus::RoundWinner => {
+                // Old game but new round
+
+                if room.player(player_id)?.is_next_round_ready {
+                    return Ok(action_result(&room, false));
+                }
+                room.player_mut(player_id)?.is_next_round_ready = true;
+
+                if room.all_players_ready() {
+                    room.next_round();
+                }
+            },
+            RoomStatus::GameWinner => {
+                // Start a new Game
+
+                if player_id != room.owner_id {
+                    return Err(GameError::NotOwner(player_id));
+                }
+                room.check_enough_players()?;
+
+                // Reset all the player scores
+                for room_player in room.players.iter_mut() {
+                    room_player.score = 0;
+                }
+                room.game_counter = room.game_counter.saturating_add(1);
+
+                room.next_leader();
+
+                room.set_status(RoomStatus::LeaderOptions);
+                room.round_counter = 1;
+                room.selected_prompt_id = None;
+                room.winner_player_id = None;
+                room.winner_finisher_id = None;
+            },
+            _ => return Err(GameError::WrongRoomStatus(room.room_status))
+        }
+
+        Ok(action_result(&room, true))
+    }
+
+    pub fn options(&self, room_id: u32, player_id: u32) -> Result<ResponseGameOptions, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        let room = &mut *room;
+        let mut rng = rand::thread_rng();
+
+        let option_ids: Vec<u16> = if room.leader_id() == player_id {
+            match &room.room_status {
+                RoomStatus::LeaderOptions => room.deal_prompt_options(self.prompts.len(), &mut rng),
+                RoomStatus::LeaderPick => {
+                    // Finishers, sorted so that their order does not give away who submitted them
+                    let mut submitted_finisher_ids = room.submitted_finisher_ids();
+                    submitted_finisher_ids.sort();
+                    submitted_finisher_ids
+                },
+                _ => return Err(GameError::WrongRoomStatus(room.room_status))
+            }
+        } else {
+            match &room.room_status {
+                RoomStatus::LackeyOptions => room.deal_finishers(player_id, self.finishers.len(), &mut rng)?,
+                _ => return Err(GameError::WrongRoomStatus(room.room_status))
+            }
+        };
+
+        let options = option_ids.iter().map(|&option_id| {
+            let option_text = match room.room_status {
+                RoomStatus::LeaderOptions => self.prompt_text(option_id),
+                _ => self.finisher_text(option_id)
+            };
+
+            ResponseGameOptionsOption {
+                option_id,
+                option_text
+            }
+        }).collect();
+
+        Ok(ResponseGameOptions {
+            options,
+            result: action_result(room, false)
+        })
+    }
+
+    pub fn pick(&self, room_id: u32, player_id: u32, option_id: u16) -> Result<ResponseGameAction, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        if room.leader_id() == player_id {
+            match &room.room_status {
+                RoomStatus::LeaderOptions => room.select_prompt(option_id)?,
+                RoomStatus::LeaderPick => room.select_winner(option_id)?,
+                _ => return Err(GameError::WrongRoomStatus(room.room_status))
+            }
+        } else {
+            match &room.room_status {
+                RoomStatus::LackeyOptions => {
+                    room.submit_finisher(player_id, option_id)?;
+
+                    if room.all_lackeys_submitted() {
+                        room.set_status(RoomStatus::LeaderPick);
+                    }
+                },
+                _ => return Err(GameError::WrongRoomStatus(room.room_status))
+            }
+        }
+
+        Ok(action_result(&room, true))
+    }
+
+    /// Every round won in the room, across all its games
+    pub fn history(&self, room_id: u32, player_id: u32) -> Result<ResponseRoomHistory, GameError> {
+        let room = self.room(room_id)?;
+        let room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        let rounds = room.rounds.iter().map(|round| {
+            let finishers = round.submissions.iter().map(|submission| ResponseRoomHistoryFinisher {
+                player_id: submission.player_id,
+                player_name: submission.player_name.clone(),
+                finisher_text: self.finisher_text(submission.finisher_id),
+                is_winner: submission.finisher_id == round.winner_finisher_id
+            }).collect();
+
+            ResponseRoomHistoryRound {
+                game_counter: round.game_counter,
+                round_counter: round.round_counter,
+                leader_id: round.leader_id,
+                leader_name: round.leader_name.clone(),
+                prompt_text: round.prompt_id.map(|prompt_id| self.prompt_text(prompt_id)),
+                finishers,
+                winner_player_id: round.winner_player_id
+            }
+        }).collect();
+
+        Ok(ResponseRoomHistory { rounds })
+    }
+
+    /// Moves the game along in the rooms where the current phase ran out of time, making random choices
+    /// for the players that did not make theirs. Returns the rooms that changed.
+    pub fn expire_phases(&self) -> Vec<u32> {
+        let rooms: Vec<Arc<Mutex<Room>>> = self.rooms.read().unwrap().by_id.values().cloned().collect();
+        let mut rng = rand::thread_rng();
+
+        let mut changed_room_ids = vec![];
+        for room in rooms {
+            let mut room = room.lock().unwrap();
+            if room.phase_time_left() != Some(Duration::ZERO) {
+                continue;
+            }
+
+            println!("PhaseTimer - Room {} ran out of time on {}", room.id, room.room_status);
+            if let Err(error) = room.expire_phase(self.prompts.len(), self.finishers.len(), &mut rng) {
+                println!("PhaseTimer - Room {}: {}", room.id, error);
+            }
+            changed_room_ids.push(room.id);
+        }
+
+        changed_room_ids
+    }
+
+    /// Moves on without waiting for the players that have not submitted a finisher or are not ready for the next round.
+    /// The owner can do it at any time, the leader only once the room has been waiting for a while.
+    pub fn force_advance(&self, room_id: u32, player_id: u32) -> Result<ResponseGameAction, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        if player_id != room.owner_id {
+            if player_id != room.leader_id() {
+                return Err(GameError::NotOwner(player_id));
+            }
+
+            let waited = room.status_changed_at.elapsed();
+            if waited < LEADER_FORCE_ADVANCE_WAIT {
+                return Err(GameError::TooEarly((LEADER_FORCE_ADVANCE_WAIT - waited).as_secs() + 1));
+            }
+        }
+
+        match room.room_status {
+            RoomStatus::LackeyOptions => {
+                // The leader needs something to pick from
+                if room.players.iter().all(|p| p.submitted_finisher_id.is_none()) {
+                    return Err(GameError::NoFinishersSubmitted);
+                }
+
+                room.set_status(RoomStatus::LeaderPick);
+            },
+            RoomStatus::RoundWinner => room.next_round(),
+            _ => return Err(GameError::WrongRoomStatus(room.room_status))
+        }
+
+        Ok(action_result(&room, true))
+    }
+
+    pub fn leave_room(&self, room_id: u32, player_id: u32) -> Result<ResponseGameAction, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        // An empty room is left for reap_idle_rooms
+        room.remove_player(player_id);
+        room.skip_missing_players();
+
+        Ok(action_result(&room, true))
+    }
+
+    pub fn kick_player(&self, room_id: u32, player_id: u32, target_player_id: u32, ban: bool) -> Result<ResponseGameAction, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        if player_id != room.owner_id {
+            return Err(GameError::NotOwner(player_id));
+        }
+
+        // Owners leave through RoomLeave
+        if target_player_id == player_id {
+            return Err(GameError::InvalidData);
+        }
+
+        let target_player_name = name_key(&room.player(target_player_id)?.name);
+        if ban && !room.banned_names.contains(&target_player_name) {
+            room.banned_names.push(target_player_name);
+        }
+
+        room.remove_player(target_player_id);
+        room.skip_missing_players();
+
+        Ok(action_result(&room, true))
+    }
+
+    pub fn transfer_ownership(&self, room_id: u32, player_id: u32, target_player_id: u32) -> Result<ResponseGameAction, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        if player_id != room.owner_id {
+            return Err(GameError::NotOwner(player_id));
+        }
+
+        room.player(target_player_id)?;
+        let state_changed = room.owner_id != target_player_id;
+        room.owner_id = target_player_id;
+
+        Ok(action_result(&room, state_changed))
+    }
+
+    /// No one can join a locked room, not even players that were kicked without a ban
+    pub fn lock_room(&self, room_id: u32, player_id: u32, is_locked: bool) -> Result<ResponseGameAction, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        if player_id != room.owner_id {
+            return Err(GameError::NotOwner(player_id));
+        }
+
+        let state_changed = room.is_locked != is_locked;
+        room.is_locked = is_locked;
+
+        Ok(action_result(&room, state_changed))
+    }
+
+    /// The owner can change the settings before the game starts, the ones not sent stay as they are
+    pub fn update_settings(&self, room_id: u32, player_id: u32, settings_update: RoomSettingsUpdate) -> Result<ResponseGameAction, GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+        room.player(player_id)?;
+
+        if player_id != room.owner_id {
+            return Err(GameError::NotOwner(player_id));
+        }
+
+        if room.room_status != RoomStatus::Waiting {
+            return Err(GameError::WrongRoomStatus(room.room_status));
+        }
+
+        let settings = updated_settings(&room.settings, settings_update);
+        validate_settings(&settings)?;
+        if room.players.len() > usize::from(settings.max_players) {
+            return Err(GameError::InvalidData);
+        }
+
+        room.settings = settings;
+
+        Ok(action_result(&room, true))
+    }
+
+    /// Counts as a check from the player, for the clients that get the room state pushed instead of asking for it
+    pub fn touch(&self, room_id: u32, player_id: u32) -> Result<(), GameError> {
+        let room = self.room(room_id)?;
+        let mut room = room.lock().unwrap();
+
+        room.player_mut(player_id)?.last_check = Instant::now();
+
+        Ok(())
+    }
+
+    /// Marks the players that have not checked in a while as disconnected, so the game goes on without them,
+    /// and removes them from their room after a longer while. Returns the rooms that changed, and the room and id
+    /// of every removed player.
+    pub fn reap_idle_players(&self, disconnect_after: Duration, remove_after: Duration) -> (Vec<u32>, Vec<(u32, u32)>) {
+        let rooms: Vec<Arc<Mutex<Room>>> = self.rooms.read().unwrap().by_id.values().cloned().collect();
+
+        let mut changed_room_ids = vec![];
+        let mut removed_players = vec![];
+        for room in rooms {
+            let mut room = room.lock().unwrap();
+            let mut changed = false;
+
+            for room_player in room.players.iter_mut() {
+                let is_connected = room_player.last_check.elapsed() < disconnect_after;
+                if room_player.is_connected != is_connected {
+                    println!("Reaper - Player {} is {}", room_player.id, if is_connected { "back" } else { "disconnected" });
+                    room_player.is_connected = is_connected;
+                    changed = true;
+                }
+            }
+
+            let idle_player_ids: Vec<u32> = room.players.iter()
+                .filter(|p| p.last_check.elapsed() >= remove_after)
+                .map(|p| p.id)
+                .collect();
+            for idle_player_id in idle_player_ids {
+                println!("Reaper - Removing player {} from room {}", idle_player_id, room.id);
+                room.remove_player(idle_player_id);
+                removed_players.push((room.id, idle_player_id));
+                changed = true;
+            }
+
+            // Empty rooms are left for reap_idle_rooms
+            if changed && !room.players.is_empty() {
+                room.skip_missing_players();
+                changed_room_ids.push(room.id);
+            }
+        }
+
+        (changed_room_ids, removed_players)
+    }
+
+    /// Removes the rooms where no player has checked in a while, or with no players at all,
+    /// along with everything they own. Returns the removed rooms.
+    pub fn reap_idle_rooms(&self, remove_after: Duration) -> Vec<u32> {
+        let mut rooms = self.rooms.write().unwrap();
+
+        let idle_rooms: Vec<(u32, String, usize)> = rooms.by_id.values()
+            .filter_map(|room| {
+                let room = room.lock().unwrap();
+                (room.idle_time() >= remove_after).then(|| (room.id, room.code.clone(), room.players.len()))
+            })
+            .collect();
+
+        for (room_id, room_code, room_player_count) in &idle_rooms {
+            rooms.by_id.remove(room_id);
+            rooms.ids_by_code.remove(room_code);
+            println!("RoomGC - Removed room {} with {} players, {} rooms left", room_id, room_player_count, rooms.by_id.len());
+        }
+
+        idle_rooms.into_iter().map(|(room_id, _, _)| room_id).collect()
+    }
+
+    /// Saves every room, with its players, hands and decks
+    pub fn save(&self, room_store: &dyn RoomStore) -> io::Result<()> {
+        let rooms: Vec<Arc<Mutex<Room>>> = self.rooms.read().unwrap().by_id.values().cloned().collect();
+
+        // One room at a time, so that no room waits for the rest to be saved
+        let snapshot_rooms: Vec<serde_json::Value> = rooms.iter()
+            .map(|room| serde_json::to_value(&*room.lock().unwrap()))
+            .collect::<Result<_, _>>()?;
+
+        room_store.save(&serde_json::to_string(&snapshot_rooms)?)
+    }
+
+    /// Brings back the rooms saved before, as they were, so players can go on with the same ids and tokens.
+    /// Returns how many rooms were restored.
+    ///
+    /// Cards are saved by their position in the deck, so the prompts and finishers files must not change in between.
+    pub fn restore(&self, room_store: &dyn RoomStore) -> io::Result<usize> {
+        let snapshot = match room_store.load()? {
+            Some(snapshot) => snapshot,
+            None => return Ok(0)
+        };
+        let snapshot_rooms: Vec<Room> = serde_json::from_str(&snapshot)?;
+
+        let mut rooms = self.rooms.write().unwrap();
+        let restored_room_count = snapshot_rooms.len();
+        for room in snapshot_rooms {
+            rooms.ids_by_code.insert(room.code.clone(), room.id);
+            rooms.by_id.insert(room.id, Arc::new(Mutex::new(room)));
+        }
+
+        Ok(restored_room_count)
+    }
+
+    // Codes are shown in uppercase, but players type them however they like
+    fn room_id_by_code(&self, room_code: &str) -> Result<u32, GameError> {
+        let normalized_room_code = room_code.trim().to_uppercase();
+        if normalized_room_code.is_empty() || normalized_room_code.len() > ROOM_CODE_LENGTH {
+            return Err(GameError::InvalidData);
+        }
+
+        match self.rooms.read().unwrap().ids_by_code.get(&normalized_room_code) {
+            Some(&room_id) => Ok(room_id),
+            None => Err(GameError::RoomCodeNotFound(normalized_room_code))
+        }
+    }
+
+    fn room(&self, room_id: u32) -> Result<Arc<Mutex<Room>>, GameError> {
+        if room_id == 0 {
+            return Err(GameError::InvalidData);
+        }
+
+        self.rooms.read().unwrap().by_id.get(&room_id).cloned().ok_or(GameError::RoomNotFound(room_id))
+    }
+
+    fn room_check_response(&self, room: &Room) -> ResponseRoomCheck {
+        let leader_id = room.leader_id();
+
+        let players_in_room_response = room.players.iter().map(|room_player| {
+            let is_finisher_ready = if leader_id == room_player.id {
+                None
+            } else {
+                Some(room_player.submitted_finisher_id.is_some())
+            };
+
+            let is_next_round_ready = match &room.room_status {
+                RoomStatus::RoundWinner => Some(room_player.is_next_round_ready),
+                _ => None
+            };
+
+            ResponseRoomCheckPlayer {
+                player_id: room_player.id,
+                player_name: room_player.name.to_string(),
+                score: room_player.score,
+                is_finisher_ready,
+                is_next_round_ready,
+                last_check: u16::try_from(room_player.last_check.elapsed().as_secs()).unwrap_or(u16::MAX),
+                is_connected: room_player.is_connected
+            }
+        }).collect();
+
+        let response_prompt_text = match &room.room_status {
+            RoomStatus::LackeyOptions | RoomStatus::LeaderPick | RoomStatus::RoundWinner => {
+                room.selected_prompt_id.map(|prompt_id| self.prompt_text(prompt_id))
+            },
+            _ => None
+        };
+
+        let response_finishers = match &room.room_status {
+            RoomStatus::RoundWinner => {
+                let converted_finishers = room.players.iter()
+                    .filter_map(|room_player| room_player.submitted_finisher_id.map(|finisher_id| ResponseRoomCheckFinisher {
+                        player_name: room_player.name.clone(),
+                        finisher_text: self.finisher_text(finisher_id),
+                        is_winner: room.winner_player_id == Some(room_player.id)
+                    }))
+                    .collect();
+                Some(converted_finishers)
+            },
+            _ => None
+        };
+
+        let response_standings: Option<Vec<ResponseRoomCheckStanding>> = match &room.room_status {
+            RoomStatus::GameWinner => {
+                let converted_standings = room.standings().into_iter()
+                    .map(|(rank, room_player)| ResponseRoomCheckStanding {
+                        player_id: room_player.id,
+                        player_name: room_player.name.clone(),
+                        score: room_player.score,
+                        rank,
+                        rounds_won: u8::try_from(room.game_rounds().filter(|round| round.winner_player_id == room_player.id).count()).unwrap_or(u8::MAX)
+                    })
+                    .collect();
+                Some(converted_standings)
+            },
+            _ => None
+        };
+
+        let response_game_winner_ids = response_standings.as_ref().map(|standings| {
+            standings.iter().filter(|standing| standing.rank == 1).map(|standing| standing.player_id).collect()
+        });
+
+        // Groups of players sharing a rank, best ranks first
+        let response_tie_groups = response_standings.as_ref().map(|standings| {
+            let mut tie_groups: Vec<Vec<u32>> = vec![];
+            for (position, standing) in standings.iter().enumerate() {
+                let is_tied_with_previous = position > 0 && standings[position - 1].rank == standing.rank;
+                match tie_groups.last_mut() {
+                    Some(tie_group) if is_tied_with_previous => tie_group.push(standing.player_id),
+                    _ => tie_groups.push(vec![standing.player_id])
+                }
+            }
+            tie_groups.retain(|tie_group| tie_group.len() > 1);
+            tie_groups
+        });
+
+        let response_rounds = match &room.room_status {
+            RoomStatus::GameWinner => {
+                let converted_rounds = room.game_rounds()
+                    .map(|round| ResponseRoomCheckRound {
+                        round_counter: round.round_counter,
+                        prompt_text: round.prompt_id.map(|prompt_id| self.prompt_text(prompt_id)),
+                        winner_player_id: round.winner_player_id,
+                        winner_player_name: round.winner_player_name.clone(),
+                        winner_finisher_text: self.finisher_text(round.winner_finisher_id)
+                    })
+                    .collect();
+                Some(converted_rounds)
+            },
+            _ => None
+        };
+
+        ResponseRoomCheck {
+            players: players_in_room_response,
+            room_status: room.room_status.to_string(),
+            owner_id: room.owner_id,
+            is_locked: room.is_locked,
+            leader_id,
+            round_counter: room.round_counter,
+            round_total: room.settings.round_total,
+            settings: room.settings,
+            phase_seconds_left: room.phase_time_left().map(|time_left| u16::try_from(time_left.as_secs()).unwrap_or(u16::MAX)),
+            prompt_text: response_prompt_text,
+            finishers: response_finishers,
+            game_winner_ids: response_game_winner_ids,
+            standings: response_standings,
+            tie_groups: response_tie_groups,
+            rounds: response_rounds
+        }
+    }
+
+    fn prompt_text(&self, prompt_id: u16) -> String {
+        self.prompts.get(usize::from(prompt_id)).cloned().unwrap_or_default()
+    }
+
+    fn finisher_text(&self, finisher_id: u16) -> String {
+        self.finishers.get(usize::from(finisher_id)).cloned().unwrap_or_default()
+    }
+}
+
+fn action_result(room: &Room, state_changed: bool) -> ResponseGameAction {
+    ResponseGameAction {
+        room_status: room.room_status.to_string(),
+        round_counter: room.round_counter,
+        round_total: room.settings.round_total,
+        state_changed
+    }
+}
+
+fn updated_settings(settings: &RoomSettings, settings_update: RoomSettingsUpdate) -> RoomSettings {
+    let phase_timers = &settings.phase_timers;
+    let phase_timers_update = settings_update.phase_timers.unwrap_or_default();
+
+    RoomSettings {
+        round_total: settings_update.round_total.unwrap_or(settings.round_total),
+        hand_size: settings_update.hand_size.unwrap_or(settings.hand_size),
+        prompt_option_count: settings_update.prompt_option_count.unwrap_or(settings.prompt_option_count),
+        min_players: settings_update.min_players.unwrap_or(settings.min_players),
+        max_players: settings_update.max_players.unwrap_or(settings.max_players),
+        game_mode: settings_update.game_mode.unwrap_or(settings.game_mode),
+        score_to_win: settings_update.score_to_win.unwrap_or(settings.score_to_win),
+        tie_break: settings_update.tie_break.unwrap_or(settings.tie_break),
+        phase_timers: PhaseTimers {
+            leader_options: phase_timers_update.leader_options.unwrap_or(phase_timers.leader_options),
+            lackey_options: phase_timers_update.lackey_options.unwrap_or(phase_timers.lackey_options),
+            leader_pick: phase_timers_update.leader_pick.unwrap_or(phase_timers.leader_pick),
+            round_winner: phase_timers_update.round_winner.unwrap_or(phase_timers.round_winner)
+        }
+    }
+}
+
+fn validate_settings(settings: &RoomSettings) -> Result<(), GameError> {
+    let phase_timers = &settings.phase_timers;
+    let all_phase_seconds = [phase_timers.leader_options, phase_timers.lackey_options, phase_timers.leader_pick, phase_timers.round_winner];
+
+    let is_valid = (1..=MAX_ROUND_TOTAL).contains(&settings.round_total)
+        && (1..=MAX_HAND_SIZE).contains(&settings.hand_size)
+        && (1..=MAX_PROMPT_OPTION_COUNT).contains(&settings.prompt_option_count)
+        && MIN_PLAYERS <= settings.min_players
+        && settings.min_players <= settings.max_players
+        && settings.max_players <= MAX_PLAYERS
+        && (1..=MAX_SCORE_TO_WIN).contains(&settings.score_to_win)
+        && all_phase_seconds.iter().all(|&phase_seconds| phase_seconds <= MAX_PHASE_SECONDS);
+
+    if is_valid { Ok(()) } else { Err(GameError::InvalidData) }
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        RoomSettings {
+            round_total: 10,
+            hand_size: 8,
+            prompt_option_count: 3,
+            min_players: MIN_PLAYERS,
+            max_players: 12,
+            game_mode: GameMode::Rounds,
+            score_to_win: 5,
+            tie_break: TieBreak::Shared,
+            phase_timers: PhaseTimers::default()
+        }
+    }
+}
+
+impl Default for PhaseTimers {
+    fn default() -> Self {
+        PhaseTimers {
+            leader_options: 60,
+            lackey_options: 90,
+            leader_pick: 60,
+            round_winner: 30
+        }
+    }
+}
+
+// Zero is never issued, as the handlers take it for a missing id
+fn random_id<R: Rng>(rng: &mut R) -> u32 {
+    rng.gen_range(1..=u32::MAX)
+}
+
+impl Rooms {
+    fn unused_id<R: Rng>(&self, rng: &mut R) -> u32 {
+        loop {
+            let room_id = random_id(rng);
+            if !self.by_id.contains_key(&room_id) {
+                return room_id;
+            }
+        }
+    }
+
+    fn unused_code<R: Rng>(&self, rng: &mut R) -> String {
+        loop {
+            let room_code: String = (0..ROOM_CODE_LENGTH)
+                .map(|_| char::from(*ROOM_CODE_ALPHABET.choose(rng).unwrap()))
+                .collect();
+            if !self.ids_by_code.contains_key(&room_code) {
+                return room_code;
+            }
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RoomStatus {
+    Waiting,
+    LeaderOptions,
+    LackeyOptions,
+    LeaderPick,
+    RoundWinner,
+    GameWinner
+}
+
+impl fmt::Display for RoomStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoomStatus::Waiting => write!(f, "WAITING"),
+            RoomStatus::LeaderOptions => write!(f, "LEADER_OPTIONS"),
+            RoomStatus::LackeyOptions => write!(f, "LACKEY_OPTIONS"),
+            RoomStatus::LeaderPick => write!(f, "LEADER_PICK"),
+            RoomStatus::RoundWinner => write!(f, "ROUND_WINNER"),
+            RoomStatus::GameWinner => write!(f, "GAME_WINNER")
+        }
+    }
+}
+
+/// A room owns everything about its game, so it is always created, changed and removed as a whole
+#[derive(Serialize, Deserialize)]
+struct Room {
+    id: u32,
+    code: String,
+    room_status: RoomStatus,
+    #[serde(with = "instant_age")]
+    status_changed_at: Instant,
+    settings: RoomSettings,
+    owner_id: u32,
+    round_counter: u8,
+    leader_player_position: u8,
+    selected_prompt_id: Option<u16>,
+    winner_player_id: Option<u32>,
+    winner_finisher_id: Option<u16>,
+    players: Vec<Player>, // In turn order
+    prompt_options: Vec<u16>, // Offered to the leader this round
+    available_prompts: Vec<u16>,
+    available_finishers: Vec<u16>,
+    is_locked: bool,
+    banned_names: Vec<String>, // As their name_key
+    game_counter: u8,
+    rounds: Vec<Round> // Of every game played in the room, as they were won
+}
+
+// Names are kept, as the players might leave the room afterwards
+#[derive(Serialize, Deserialize)]
+struct Round {
+    game_counter: u8,
+    round_counter: u8,
+    leader_id: u32,
+    leader_name: String,
+    prompt_id: Option<u16>,
+    submissions: Vec<Submission>,
+    winner_player_id: u32,
+    winner_player_name: String,
+    winner_finisher_id: u16
+}
+
+#[derive(Serialize, Deserialize)]
+struct Submission {
+    player_id: u32,
+    player_name: String,
+    finisher_id: u16
+}
+
+impl Room {
+    fn leader_id(&self) -> u32 {
+        self.players.get(usize::from(self.leader_player_position)).map(|p| p.id).unwrap_or_default()
+    }
+
+    // Since the most recent check from any of the players
+    fn idle_time(&self) -> Duration {
+        self.players.iter().map(|p| p.last_check.elapsed()).min().unwrap_or(Duration::MAX)
+    }
+
+    // Disconnected players are skipped, unless no one is left
+    fn next_leader(&mut self) {
+        let room_player_count = u8::try_from(self.players.len()).unwrap_or(u8::MAX);
+        for _ in 0..room_player_count {
+            self.leader_player_position = (self.leader_player_position + 1) % room_player_count;
+            if self.players[usize::from(self.leader_player_position)].is_connected {
+                break;
+            }
+        }
+    }
+
+    fn set_status(&mut self, room_status: RoomStatus) {
+        self.room_status = room_status;
+        self.status_changed_at = Instant::now();
+    }
+
+    fn phase_time_left(&self) -> Option<Duration> {
+        let phase_seconds = match self.room_status {
+            RoomStatus::LeaderOptions => self.settings.phase_timers.leader_options,
+            RoomStatus::LackeyOptions => self.settings.phase_timers.lackey_options,
+            RoomStatus::LeaderPick => self.settings.phase_timers.leader_pick,
+            RoomStatus::RoundWinner => self.settings.phase_timers.round_winner,
+            _ => 0
+        };
+
+        // Zero means no time limit
+        if phase_seconds == 0 {
+            return None;
+        }
+
+        Some(Duration::from_secs(u64::from(phase_seconds)).saturating_sub(self.status_changed_at.elapsed()))
+    }
+
+    /// Makes the choices that the players did not make in time, and moves on to the next phase
+    fn expire_phase<R: Rng>(&mut self, prompt_count: usize, finisher_count: usize, rng: &mut R) -> Result<(), GameError> {
+        match self.room_status {
+            RoomStatus::LeaderOptions => {
+                let prompt_options = self.deal_prompt_options(prompt_count, rng);
+                match prompt_options.choose(rng) {
+                    Some(&prompt_id) => self.select_prompt(prompt_id)?,
+                    // Nothing to pick from, so just wait another while
+                    None => self.set_status(RoomStatus::LeaderOptions)
+                }
+            },
+            RoomStatus::LackeyOptions => {
+                let leader_id = self.leader_id();
+                let idle_lackey_ids: Vec<u32> = self.players.iter()
+                    .filter(|p| p.id != leader_id && p.submitted_finisher_id.is_none())
+                    .map(|p| p.id)
+                    .collect();
+
+                for idle_lackey_id in idle_lackey_ids {
+                    let finishers = self.deal_finishers(idle_lackey_id, finisher_count, rng)?;
+                    if let Some(&finisher_id) = finishers.choose(rng) {
+                        self.submit_finisher(idle_lackey_id, finisher_id)?;
+                    }
+                }
+
+                if self.submitted_finisher_ids().is_empty() {
+                    // No lackeys to submit anything, so the room waits for players instead of timing out again and again
+                    self.take_back_round();
+                    self.set_status(RoomStatus::Waiting);
+                } else {
+                    self.set_status(RoomStatus::LeaderPick);
+                }
+            },
+            RoomStatus::LeaderPick => {
+                match self.submitted_finisher_ids().choose(rng) {
+                    Some(&finisher_id) => self.select_winner(finisher_id)?,
+                    None => self.next_round()
+                }
+            },
+            RoomStatus::RoundWinner => self.next_round(),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // Tops up the prompts offered to the leader, returning them
+    fn deal_prompt_options<R: Rng>(&mut self, prompt_count: usize, rng: &mut R) -> Vec<u16> {
+        for _ in self.prompt_options.len()..usize::from(self.settings.prompt_option_count) {
+            if self.available_prompts.is_empty() {
+                // Refill, excluding the prompts already offered this round
+                let mut prompt_ids: Vec<u16> = (0..prompt_count)
+                    .map(|id| id as u16)
+                    .filter(|prompt_id| !self.prompt_options.contains(prompt_id))
+                    .collect();
+                prompt_ids.shuffle(rng);
+                self.available_prompts.append(&mut prompt_ids);
+            }
+
+            match self.available_prompts.pop() {
+                Some(prompt_id) => self.prompt_options.push(prompt_id),
+                None => break
+            }
+        }
+
+        self.prompt_options.clone()
+    }
+
+    // Tops up the hand of a lackey, returning it
+    fn deal_finishers<R: Rng>(&mut self, player_id: u32, finisher_count: usize, rng: &mut R) -> Result<Vec<u16>, GameError> {
+        let player_finisher_count = self.player(player_id)?.finishers.len();
+
+        for _ in player_finisher_count..usize::from(self.settings.hand_size) {
+            if self.available_finishers.is_empty() {
+                // Refill, excluding the finishers already in the hands of the room players
+                let held_finishers: Vec<u16> = self.players.iter().flat_map(|p| p.finishers.iter().cloned()).collect();
+
+                let mut finisher_ids: Vec<u16> = (0..finisher_count)
+                    .map(|id| id as u16)
+                    .filter(|finisher_id| !held_finishers.contains(finisher_id))
+                    .collect();
+                finisher_ids.shuffle(rng);
+                self.available_finishers.append(&mut finisher_ids);
+            }
+
+            match self.available_finishers.pop() {
+                Some(finisher_id) => self.player_mut(player_id)?.finishers.push(finisher_id),
+                None => break
+            }
+        }
+
+        Ok(self.player(player_id)?.finishers.clone())
+    }
+
+    fn submitted_finisher_ids(&self) -> Vec<u16> {
+        self.players.iter().filter_map(|p| p.submitted_finisher_id).collect()
+    }
+
+    fn select_prompt(&mut self, prompt_id: u16) -> Result<(), GameError> {
+        if !self.prompt_options.contains(&prompt_id) {
+            return Err(GameError::OptionNotFound(prompt_id));
+        }
+
+        // Need to clean them so that the lackeys can place new cards
+        for room_player in self.players.iter_mut() {
+            room_player.submitted_finisher_id = None;
+        }
+        self.prompt_options.clear();
+
+        self.set_status(RoomStatus::LackeyOptions);
+        self.selected_prompt_id = Some(prompt_id);
+
+        Ok(())
+    }
+
+    fn submit_finisher(&mut self, player_id: u32, finisher_id: u16) -> Result<(), GameError> {
+        let player = self.player_mut(player_id)?;
+        if !player.finishers.contains(&finisher_id) {
+            return Err(GameError::OptionNotFound(finisher_id));
+        }
+
+        if player.submitted_finisher_id.is_some() {
+            return Err(GameError::FinisherAlreadySubmitted);
+        }
+
+        player.finishers.retain(|&f| f != finisher_id);
+        player.submitted_finisher_id = Some(finisher_id);
+
+        Ok(())
+    }
+
+    fn select_winner(&mut self, finisher_id: u16) -> Result<(), GameError> {
+        let winner_player = match self.players.iter_mut().find(|p| p.submitted_finisher_id == Some(finisher_id)) {
+            Some(winner_player) => winner_player,
+            None => return Err(GameError::OptionNotFound(finisher_id))
+        };
+        winner_player.score += 1;
+        let winner_player_id = winner_player.id;
+        let winner_player_name = winner_player.name.clone();
+
+        let leader_id = self.leader_id();
+        let submissions = self.players.iter()
+            .filter_map(|p| p.submitted_finisher_id.map(|submitted_finisher_id| Submission {
+                player_id: p.id,
+                player_name: p.name.clone(),
+                finisher_id: submitted_finisher_id
+            }))
+            .collect();
+        self.rounds.push(Round {
+            game_counter: self.game_counter,
+            round_counter: self.round_counter,
+            leader_id,
+            leader_name: self.player(leader_id).map(|p| p.name.clone()).unwrap_or_default(),
+            prompt_id: self.selected_prompt_id,
+            submissions,
+            winner_player_id,
+            winner_player_name,
+            winner_finisher_id: finisher_id
+        });
+
+        // No player is ready now (including leader)
+        for room_player in self.players.iter_mut() {
+            room_player.is_next_round_ready = false;
+        }
+
+        self.set_status(RoomStatus::RoundWinner);
+        self.winner_player_id = Some(winner_player_id);
+        self.winner_finisher_id = Some(finisher_id);
+
+        Ok(())
+    }
+
+    fn is_playing_round(&self) -> bool {
+        matches!(self.room_status, RoomStatus::LeaderOptions | RoomStatus::LackeyOptions | RoomStatus::LeaderPick)
+    }
+
+    // Disconnected lackeys are not waited for, but at least one finisher is needed for the leader to pick
+    fn all_lackeys_submitted(&self) -> bool {
+        let leader_id = self.leader_id();
+        let mut lackeys = self.players.iter().filter(|p| p.id != leader_id);
+
+        lackeys.clone().any(|p| p.submitted_finisher_id.is_some())
+            && lackeys.all(|p| !p.is_connected || p.submitted_finisher_id.is_some())
+    }
+
+    fn check_enough_players(&self) -> Result<(), GameError> {
+        let room_player_count = u8::try_from(self.players.len()).unwrap_or(u8::MAX);
+        if room_player_count < self.settings.min_players {
+            return Err(GameError::NotEnoughPlayers(room_player_count));
+        }
+
+        Ok(())
+    }
+
+    fn all_players_ready(&self) -> bool {
+        self.players.iter().any(|p| p.is_next_round_ready)
+            && self.players.iter().all(|p| !p.is_connected || p.is_next_round_ready)
+    }
+
+    fn next_round(&mut self) {
+        self.selected_prompt_id = None;
+        self.winner_player_id = None;
+        self.winner_finisher_id = None;
+
+        let is_game_over = match self.settings.game_mode {
+            GameMode::Rounds => self.round_counter >= self.settings.round_total,
+            GameMode::Points => self.players.iter().any(|p| p.score >= self.settings.score_to_win)
+        };
+        let is_tied = self.standings().iter().filter(|&&(rank, _)| rank == 1).count() > 1;
+
+        if !is_game_over || (is_tied && self.settings.tie_break == TieBreak::SuddenDeath) {
+            // Next round (the extra ones for a sudden death go past the round total)
+
+            self.round_counter = self.round_counter.saturating_add(1);
+            self.next_leader();
+
+            // Too few players are left, so the room waits for more before the owner starts the round again
+            if self.check_enough_players().is_err() {
+                self.set_status(RoomStatus::Waiting);
+            } else {
+                self.set_status(RoomStatus::LeaderOptions);
+            }
+        } else {
+            // Game end
+
+            // Leader and turn changes happen later during GameWinner.
+
+            self.set_status(RoomStatus::GameWinner);
+        }
+    }
+
+    // The rounds of the game being played, or just finished
+    fn game_rounds(&self) -> impl Iterator<Item = &Round> {
+        self.rounds.iter().filter(|round| round.game_counter == self.game_counter)
+    }
+
+    /// Players by score, with their rank. Tied players share the rank, and the next one skips as many ranks.
+    fn standings(&self) -> Vec<(u8, &Player)> {
+        let mut ranked_players: Vec<&Player> = self.players.iter().collect();
+        ranked_players.sort_by_key(|p| Reverse(p.score));
+
+        let mut standings: Vec<(u8, &Player)> = vec![];
+        for (position, room_player) in ranked_players.into_iter().enumerate() {
+            let rank = match standings.last() {
+                Some(&(last_rank, last_player)) if last_player.score == room_player.score => last_rank,
+                _ => u8::try_from(position + 1).unwrap_or(u8::MAX)
+            };
+            standings.push((rank, room_player));
+        }
+
+        standings
+    }
+
+    /// Moves the game along as if the disconnected or departed players had already done their part
+    fn skip_missing_players(&mut self) {
+        let leader_is_connected = self.players.get(usize::from(self.leader_player_position)).is_some_and(|p| p.is_connected);
+        let someone_connected = self.players.iter().any(|p| p.is_connected);
+        if self.is_playing_round() && !leader_is_connected && someone_connected {
+            self.restart_round_with_next_leader();
+        }
+
+        match self.room_status {
+            RoomStatus::LackeyOptions if self.all_lackeys_submitted() => self.set_status(RoomStatus::LeaderPick),
+            // Everyone that submitted a finisher is gone, so the leader has nothing to pick from
+            RoomStatus::LeaderPick if self.submitted_finisher_ids().is_empty() => self.set_status(RoomStatus::LackeyOptions),
+            RoomStatus::RoundWinner if self.all_players_ready() => self.next_round(),
+            _ => {}
+        }
+
+        // A round needs a leader and a lackey, so the room waits for more players (keeping the scores and round)
+        if self.is_playing_round() && self.players.len() < 2 {
+            self.take_back_round();
+            self.set_status(RoomStatus::Waiting);
+        }
+    }
+
+    // Same round number, but the submitted finishers go back to their hands as the new leader picks a new prompt
+    fn restart_round_with_next_leader(&mut self) {
+        self.take_back_round();
+
+        self.next_leader();
+        self.set_status(RoomStatus::LeaderOptions);
+    }
+
+    fn take_back_round(&mut self) {
+        for room_player in self.players.iter_mut() {
+            if let Some(finisher_id) = room_player.submitted_finisher_id.take() {
+                room_player.finishers.push(finisher_id);
+            }
+        }
+        self.prompt_options.clear();
+        self.selected_prompt_id = None;
+    }
+
+    fn remove_player(&mut self, player_id: u32) {
+        let Some(position) = self.players.iter().position(|p| p.id == player_id) else {
+            return;
+        };
+
+        if position == usize::from(self.leader_player_position) && self.is_playing_round() {
+            self.restart_round_with_next_leader();
+        }
+
+        // Their hand goes back to the deck, their submission (if any) is gone with them
+        let player = self.players.remove(position);
+        self.available_finishers.extend(player.finishers);
+        if self.players.is_empty() {
+            return;
+        }
+
+        let leader_position = usize::from(self.leader_player_position);
+        let room_player_count = self.players.len();
+        // Keep pointing at the same leader. When the leader is the one leaving between rounds,
+        // point at the previous player, so that the next round goes to the one after them.
+        let leader_position = if position < leader_position
+            || (position == leader_position && matches!(self.room_status, RoomStatus::RoundWinner | RoomStatus::GameWinner)) {
+            (leader_position + room_player_count - 1) % room_player_count
+        } else {
+            leader_position % room_player_count
+        };
+        self.leader_player_position = u8::try_from(leader_position).unwrap_or_default();
+
+        if self.owner_id == player_id {
+            self.owner_id = self.players[0].id;
+        }
+    }
+
+    fn unused_player_id<R: Rng>(&self, rng: &mut R) -> u32 {
+        loop {
+            let player_id = random_id(rng);
+            if self.players.iter().all(|p| p.id != player_id) {
+                return player_id;
+            }
+        }
+    }
+
+    fn has_player_named(&self, player_name: &str) -> bool {
+        let player_name_key = name_key(player_name);
+        self.players.iter().any(|p| name_key(&p.name) == player_name_key)
+    }
+
+    fn player(&self, player_id: u32) -> Result<&Player, GameError> {
+        if player_id == 0 {
+            return Err(GameError::InvalidData);
+        }
+
+        self.players.iter().find(|p| p.id == player_id).ok_or(GameError::PlayerNotInRoom(player_id, self.id))
+    }
+
+    fn player_mut(&mut self, player_id: u32) -> Result<&mut Player, GameError> {
+        if player_id == 0 {
+            return Err(GameError::InvalidData);
+        }
+
+        let room_id = self.id;
+        self.players.iter_mut().find(|p| p.id == player_id).ok_or(GameError::PlayerNotInRoom(player_id, room_id))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Player {
+    id: u32,
+    name: String,
+    token: String, // Secret, unlike the id that every player in the room gets to see
+    // Snapshots saved before it existed have none, so those players just cant rejoin
+    #[serde(default)]
+    reconnect_token: String,
+    score: u8,
+    #[serde(with = "instant_age")]
+    last_check: Instant,
+    is_connected: bool,
+    finishers: Vec<u16>, // The hand
+    submitted_finisher_id: Option<u16>,
+    is_next_round_ready: bool
+}
+
+impl Player {
+    fn new(id: u32, name: &str) -> Player {
+        Player {
+            id,
+            name: name.to_string(),
+            token: Alphanumeric.sample_string(&mut rand::thread_rng(), 32),
+            reconnect_token: Alphanumeric.sample_string(&mut rand::thread_rng(), 32),
+            score: 0,
+            last_check: Instant::now(),
+            is_connected: true,
+            finishers: vec![],
+            submitted_finisher_id: None,
+            is_next_round_ready: false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+    use std::thread;
+
+    use crate::protocol::PhaseTimersUpdate;
+
+    const ONE_SECOND_TIMERS: PhaseTimers = PhaseTimers { leader_options: 1, lackey_options: 1, leader_pick: 1, round_winner: 1 };
+    const NO_TIMERS: PhaseTimers = PhaseTimers { leader_options: 0, lackey_options: 0, leader_pick: 0, round_winner: 0 };
+
+    fn new_game_engine() -> GameEngine {
+        let prompts = (0..20).map(|i| format!("Prompt {} ___", i)).collect();
+        let finishers = (0..100).map(|i| format!("Finisher {}", i)).collect();
+        GameEngine::new(prompts, finishers)
+    }
+
+    // A started game, with the owner first among the players
+    fn new_game(game_engine: &GameEngine, settings: RoomSettings, player_count: usize) -> (u32, Vec<u32>) {
+        let response_room_create = game_engine.create_room("Owner", Some(settings)).unwrap();
+        let room_id = response_room_create.room_id;
+
+        let mut player_ids = vec![response_room_create.player_id];
+        for i in 1..player_count {
+            player_ids.push(game_engine.join_room(&response_room_create.room_code, &format!("Lackey {}", i)).unwrap().player_id);
+        }
+
+        game_engine.start(room_id, player_ids[0]).unwrap();
+        (room_id, player_ids)
+    }
+
+    // Every lackey submits a finisher and the leader picks the one from the winner
+    fn play_round(game_engine: &GameEngine, room_id: u32, player_ids: &[u32], winner_id: u32) -> ResponseGameAction {
+        let leader_id = game_engine.room_state(room_id).unwrap().leader_id;
+        let prompt_options = game_engine.options(room_id, leader_id).unwrap();
+        game_engine.pick(room_id, leader_id, prompt_options.options[0].option_id).unwrap();
+
+        let mut winner_finisher_id = None;
+        for &lackey_id in player_ids.iter().filter(|&&player_id| player_id != leader_id) {
+            let finisher_id = game_engine.options(room_id, lackey_id).unwrap().options[0].option_id;
+            game_engine.pick(room_id, lackey_id, finisher_id).unwrap();
+            if lackey_id == winner_id {
+                winner_finisher_id = Some(finisher_id);
+            }
+        }
+
+        game_engine.pick(room_id, leader_id, winner_finisher_id.unwrap()).unwrap()
+    }
+
+    fn ready_everyone(game_engine: &GameEngine, room_id: u32, player_ids: &[u32]) -> ResponseGameAction {
+        player_ids.iter().map(|&player_id| game_engine.start(room_id, player_id).unwrap()).last().unwrap()
+    }
+
+    fn assert_round_won_by(game_engine: &GameEngine, room_id: u32, winner_id: u32, score: u8) {
+        let room_state = game_engine.room_state(room_id).unwrap();
+        assert_eq!(room_state.room_status, "ROUND_WINNER");
+        assert!(room_state.finishers.unwrap().iter().any(|f| f.is_winner));
+        assert_eq!(room_state.players.iter().find(|p| p.player_id == winner_id).unwrap().score, score);
+    }
+
+    #[test]
+    fn plays_a_whole_game() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { round_total: 2, phase_timers: NO_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+
+        play_round(&game_engine, room_id, &player_ids, player_ids[1]);
+        assert_round_won_by(&game_engine, room_id, player_ids[1], 1);
+
+        let response_game_action = ready_everyone(&game_engine, room_id, &player_ids);
+        assert_eq!(response_game_action.room_status, "LEADER_OPTIONS");
+        assert_eq!(response_game_action.round_counter, 2);
+        assert_eq!(game_engine.room_state(room_id).unwrap().leader_id, player_ids[1]);
+
+        play_round(&game_engine, room_id, &player_ids, player_ids[2]);
+        assert_round_won_by(&game_engine, room_id, player_ids[2], 1);
+        let response_game_action = ready_everyone(&game_engine, room_id, &player_ids);
+        assert_eq!(response_game_action.room_status, "GAME_WINNER");
+
+        let room_state = game_engine.room_state(room_id).unwrap();
+        let mut game_winner_ids = room_state.game_winner_ids.unwrap();
+        game_winner_ids.sort();
+        let mut expected_winner_ids = vec![player_ids[1], player_ids[2]];
+        expected_winner_ids.sort();
+        assert_eq!(game_winner_ids, expected_winner_ids);
+        assert_eq!(room_state.tie_groups.unwrap().len(), 1);
+        assert_eq!(room_state.rounds.unwrap().len(), 2);
+
+        // The owner starts over with every score back to zero
+        let response_game_action = game_engine.start(room_id, player_ids[0]).unwrap();
+        assert_eq!(response_game_action.room_status, "LEADER_OPTIONS");
+        assert_eq!(response_game_action.round_counter, 1);
+        assert!(game_engine.room_state(room_id).unwrap().players.iter().all(|p| p.score == 0));
+    }
+
+    #[test]
+    fn points_mode_ends_when_someone_reaches_the_score() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { game_mode: GameMode::Points, score_to_win: 2, phase_timers: NO_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 4);
+
+        for _ in 0..2 {
+            let leader_id = game_engine.room_state(room_id).unwrap().leader_id;
+            let winner_id = if leader_id == player_ids[3] { player_ids[2] } else { player_ids[3] };
+            play_round(&game_engine, room_id, &player_ids, winner_id);
+            ready_everyone(&game_engine, room_id, &player_ids);
+        }
+
+        let room_state = game_engine.room_state(room_id).unwrap();
+        assert_eq!(room_state.room_status, "GAME_WINNER");
+        assert_eq!(room_state.game_winner_ids, Some(vec![player_ids[3]]));
+    }
+
+    #[test]
+    fn sudden_death_plays_past_the_round_total() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { round_total: 2, tie_break: TieBreak::SuddenDeath, phase_timers: NO_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+
+        play_round(&game_engine, room_id, &player_ids, player_ids[1]);
+        ready_everyone(&game_engine, room_id, &player_ids);
+        play_round(&game_engine, room_id, &player_ids, player_ids[2]);
+        let response_game_action = ready_everyone(&game_engine, room_id, &player_ids);
+        assert_eq!(response_game_action.room_status, "LEADER_OPTIONS");
+        assert_eq!(response_game_action.round_counter, 3);
+
+        play_round(&game_engine, room_id, &player_ids, player_ids[1]);
+        let response_game_action = ready_everyone(&game_engine, room_id, &player_ids);
+        assert_eq!(response_game_action.room_status, "GAME_WINNER");
+        assert_eq!(game_engine.room_state(room_id).unwrap().game_winner_ids, Some(vec![player_ids[1]]));
+    }
+
+    #[test]
+    fn leader_waits_for_new_finishers_when_every_submitter_leaves() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { phase_timers: NO_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+
+        let prompt_options = game_engine.options(room_id, player_ids[0]).unwrap();
+        game_engine.pick(room_id, player_ids[0], prompt_options.options[0].option_id).unwrap();
+        let finisher_id = game_engine.options(room_id, player_ids[1]).unwrap().options[0].option_id;
+        game_engine.pick(room_id, player_ids[1], finisher_id).unwrap();
+        assert_eq!(game_engine.force_advance(room_id, player_ids[0]).unwrap().room_status, "LEADER_PICK");
+
+        game_engine.leave_room(room_id, player_ids[1]).unwrap();
+        assert_eq!(game_engine.room_state(room_id).unwrap().room_status, "LACKEY_OPTIONS");
+
+        // The remaining lackey can still submit, and the leader pick it
+        let finisher_id = game_engine.options(room_id, player_ids[2]).unwrap().options[0].option_id;
+        assert_eq!(game_engine.pick(room_id, player_ids[2], finisher_id).unwrap().room_status, "LEADER_PICK");
+        assert_eq!(game_engine.pick(room_id, player_ids[0], finisher_id).unwrap().room_status, "ROUND_WINNER");
+    }
+
+    #[test]
+    fn room_waits_for_players_when_one_is_left_mid_round() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { phase_timers: NO_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+
+        let prompt_options = game_engine.options(room_id, player_ids[0]).unwrap();
+        game_engine.pick(room_id, player_ids[0], prompt_options.options[0].option_id).unwrap();
+        assert!(game_engine.leave_room(room_id, player_ids[1]).unwrap().state_changed);
+        let response_room_kick = game_engine.kick_player(room_id, player_ids[0], player_ids[2], false).unwrap();
+        assert_eq!(response_room_kick.room_status, "WAITING");
+        assert!(response_room_kick.state_changed);
+
+        let room_state = game_engine.room_state(room_id).unwrap();
+        assert_eq!(room_state.room_status, "WAITING");
+        assert_eq!(room_state.prompt_text, None);
+    }
+
+    #[test]
+    fn room_waits_for_players_when_some_leave_after_a_round() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { phase_timers: NO_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+        play_round(&game_engine, room_id, &player_ids, player_ids[1]);
+
+        // The ones left can still get ready, but the next round waits for a third player
+        game_engine.leave_room(room_id, player_ids[2]).unwrap();
+        assert_eq!(ready_everyone(&game_engine, room_id, &player_ids[..2]).room_status, "WAITING");
+        assert!(matches!(game_engine.start(room_id, player_ids[0]), Err(GameError::NotEnoughPlayers(2))));
+
+        let room_code = game_engine.room(room_id).unwrap().lock().unwrap().code.clone();
+        game_engine.join_room(&room_code, "Late").unwrap();
+        let response_game_start = game_engine.start(room_id, player_ids[0]).unwrap();
+        assert_eq!(response_game_start.room_status, "LEADER_OPTIONS");
+        assert_eq!(response_game_start.round_counter, 2);
+        let room_state = game_engine.room_state(room_id).unwrap();
+        assert_eq!(room_state.players.iter().find(|p| p.player_id == player_ids[1]).unwrap().score, 1);
+
+        // An owner left alone is not dealt a round with no lackeys
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+        play_round(&game_engine, room_id, &player_ids, player_ids[1]);
+        game_engine.leave_room(room_id, player_ids[1]).unwrap();
+        game_engine.leave_room(room_id, player_ids[2]).unwrap();
+        assert_eq!(game_engine.force_advance(room_id, player_ids[0]).unwrap().room_status, "WAITING");
+    }
+
+    #[test]
+    fn suffixed_names_keep_within_the_length_limit() {
+        let game_engine = new_game_engine().with_duplicate_names(DuplicateNames::Suffix);
+        let response_room_create = game_engine.create_room("abcdefghijklmnop", None).unwrap();
+
+        let response_room_join = game_engine.join_room(&response_room_create.room_code, "ABCDEFGHIJKLMNOP").unwrap();
+        assert_eq!(response_room_join.player_name, "ABCDEFGHIJKL (2)");
+
+        let response_room_join = game_engine.join_room(&response_room_create.room_code, "Abcdefghijklmnop").unwrap();
+        assert_eq!(response_room_join.player_name, "Abcdefghijkl (3)");
+    }
+
+    #[test]
+    fn joins_with_the_room_code_in_any_case() {
+        let game_engine = new_game_engine();
+        let response_room_create = game_engine.create_room("Owner", None).unwrap();
+        assert!(response_room_create.room_code.bytes().all(|c| ROOM_CODE_ALPHABET.contains(&c)));
+
+        let response_room_join = game_engine.join_room(&format!(" {} ", response_room_create.room_code.to_lowercase()), "Lackey").unwrap();
+        assert_eq!(response_room_join.room_id, response_room_create.room_id);
+    }
+
+    // As if the phase had started a second ago, then lets the phase timer run
+    fn run_out_of_time(game_engine: &GameEngine, room_id: u32) -> ResponseRoomCheck {
+        let room = game_engine.room(room_id).unwrap();
+        room.lock().unwrap().status_changed_at -= Duration::from_secs(1);
+
+        assert_eq!(game_engine.expire_phases(), vec![room_id]);
+        game_engine.room_state(room_id).unwrap()
+    }
+
+    #[test]
+    fn phase_timers_make_the_missing_choices() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { phase_timers: ONE_SECOND_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+        assert!(game_engine.expire_phases().is_empty());
+
+        let room_state = run_out_of_time(&game_engine, room_id);
+        assert_eq!(room_state.room_status, "LACKEY_OPTIONS");
+        assert!(room_state.prompt_text.is_some());
+
+        // Only the lackey that did not pick gets a random finisher
+        let finisher_id = game_engine.options(room_id, player_ids[1]).unwrap().options[0].option_id;
+        game_engine.pick(room_id, player_ids[1], finisher_id).unwrap();
+        assert_eq!(run_out_of_time(&game_engine, room_id).room_status, "LEADER_PICK");
+        {
+            let room = game_engine.room(room_id).unwrap();
+            let room = room.lock().unwrap();
+            assert_eq!(room.player(player_ids[1]).unwrap().submitted_finisher_id, Some(finisher_id));
+            assert!(room.player(player_ids[2]).unwrap().submitted_finisher_id.is_some());
+        }
+
+        let room_state = run_out_of_time(&game_engine, room_id);
+        assert_eq!(room_state.room_status, "ROUND_WINNER");
+        assert_eq!(room_state.players.iter().map(|p| p.score).sum::<u8>(), 1);
+
+        let room_state = run_out_of_time(&game_engine, room_id);
+        assert_eq!(room_state.room_status, "LEADER_OPTIONS");
+        assert_eq!(room_state.round_counter, 2);
+    }
+
+    #[test]
+    fn lackey_options_without_lackeys_wait_for_players_when_out_of_time() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { phase_timers: ONE_SECOND_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+        run_out_of_time(&game_engine, room_id);
+
+        // Lackeys gone behind the back of skip_missing_players, which is where the timer used to loop
+        {
+            let room = game_engine.room(room_id).unwrap();
+            let mut room = room.lock().unwrap();
+            room.players.retain(|p| p.id == player_ids[0]);
+            room.leader_player_position = 0;
+        }
+
+        let room_state = run_out_of_time(&game_engine, room_id);
+        assert_eq!(room_state.room_status, "WAITING");
+        assert_eq!(room_state.prompt_text, None);
+        assert!(game_engine.expire_phases().is_empty());
+    }
+
+    struct MemoryRoomStore {
+        snapshot: Mutex<Option<String>>
+    }
+
+    impl RoomStore for MemoryRoomStore {
+        fn save(&self, snapshot: &str) -> io::Result<()> {
+            *self.snapshot.lock().unwrap() = Some(snapshot.to_string());
+            Ok(())
+        }
+
+        fn load(&self) -> io::Result<Option<String>> {
+            Ok(self.snapshot.lock().unwrap().clone())
+        }
+    }
+
+    #[test]
+    fn restores_rooms_in_the_middle_of_a_game() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { phase_timers: PhaseTimers { lackey_options: 60, ..NO_TIMERS }, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+        let prompt_options = game_engine.options(room_id, player_ids[0]).unwrap();
+        game_engine.pick(room_id, player_ids[0], prompt_options.options[0].option_id).unwrap();
+        let finisher_id = game_engine.options(room_id, player_ids[1]).unwrap().options[0].option_id;
+        game_engine.pick(room_id, player_ids[1], finisher_id).unwrap();
+        game_engine.options(room_id, player_ids[2]).unwrap();
+
+        let room = game_engine.room(room_id).unwrap();
+        room.lock().unwrap().status_changed_at -= Duration::from_secs(20);
+        let saved_players: Vec<(String, Vec<u16>, Option<u16>)> = room.lock().unwrap().players.iter()
+            .map(|p| (p.token.clone(), p.finishers.clone(), p.submitted_finisher_id))
+            .collect();
+        let saved_room_state = game_engine.room_state(room_id).unwrap();
+
+        let room_store = MemoryRoomStore { snapshot: Mutex::new(None) };
+        game_engine.save(&room_store).unwrap();
+        let restored_game_engine = new_game_engine();
+        assert_eq!(restored_game_engine.restore(&room_store).unwrap(), 1);
+
+        let room_state = restored_game_engine.room_state(room_id).unwrap();
+        assert_eq!(room_state.room_status, "LACKEY_OPTIONS");
+        assert_eq!(room_state.round_counter, 1);
+        assert_eq!(room_state.prompt_text, saved_room_state.prompt_text);
+        assert!((38..=40).contains(&room_state.phase_seconds_left.unwrap()));
+
+        let room = restored_game_engine.room(room_id).unwrap();
+        for (&player_id, (player_token, finishers, submitted_finisher_id)) in player_ids.iter().zip(&saved_players) {
+            restored_game_engine.authenticate(room_id, player_id, player_token).unwrap();
+            let room = room.lock().unwrap();
+            let player = room.player(player_id).unwrap();
+            assert_eq!(&player.finishers, finishers);
+            assert_eq!(&player.submitted_finisher_id, submitted_finisher_id);
+        }
+
+        // And the game goes on from there
+        let room_code = room.lock().unwrap().code.clone();
+        assert!(matches!(restored_game_engine.join_room(&room_code, "Owner"), Err(GameError::PlayerNameTaken(_))));
+        let finisher_id = saved_players[2].1[0];
+        assert_eq!(restored_game_engine.pick(room_id, player_ids[2], finisher_id).unwrap().room_status, "LEADER_PICK");
+    }
+
+    #[test]
+    fn settings_updates_keep_the_fields_left_out() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { hand_size: 5, phase_timers: PhaseTimers { leader_pick: 30, ..NO_TIMERS }, ..RoomSettings::default() };
+        let response_room_create = game_engine.create_room("Owner", Some(settings)).unwrap();
+        let room_id = response_room_create.room_id;
+        let owner_id = response_room_create.player_id;
+
+        let settings_update = RoomSettingsUpdate {
+            round_total: Some(3),
+            phase_timers: Some(PhaseTimersUpdate { round_winner: Some(10), ..PhaseTimersUpdate::default() }),
+            ..RoomSettingsUpdate::default()
+        };
+        game_engine.update_settings(room_id, owner_id, settings_update).unwrap();
+
+        let settings = game_engine.room_state(room_id).unwrap().settings;
+        assert_eq!(settings.round_total, 3);
+        assert_eq!(settings.hand_size, 5);
+        assert_eq!(settings.phase_timers.leader_pick, 30);
+        assert_eq!(settings.phase_timers.round_winner, 10);
+
+        let invalid_settings_update = RoomSettingsUpdate { min_players: Some(10), max_players: Some(5), ..RoomSettingsUpdate::default() };
+        assert!(game_engine.update_settings(room_id, owner_id, invalid_settings_update).is_err());
+        assert_eq!(game_engine.room_state(room_id).unwrap().settings.max_players, 12);
+    }
+
+    // Lives here rather than in tests/concurrency.rs, since only from here a room lock can be held
+    #[test]
+    fn rooms_progress_while_another_room_is_locked() {
+        let game_engine = Arc::new(new_game_engine());
+        let response_room_create = game_engine.create_room("Owner", None).unwrap();
+        let locked_room_id = response_room_create.room_id;
+
+        let locked_room = game_engine.room(locked_room_id).unwrap();
+        let locked_room_guard = locked_room.lock().unwrap();
+
+        // An action on the locked room waits for it...
+        let (waiting_sender, waiting_receiver) = mpsc::channel();
+        let waiting_game_engine = Arc::clone(&game_engine);
+        let waiting_room_code = response_room_create.room_code.clone();
+        let waiting = thread::spawn(move || {
+            waiting_game_engine.join_room(&waiting_room_code, "Late").unwrap();
+            waiting_sender.send(()).unwrap();
+        });
+
+        // ...while other rooms go through whole actions, being created, joined and started at the same time
+        let (room_sender, room_receiver) = mpsc::channel();
+        for _ in 0..16 {
+            let game_engine = Arc::clone(&game_engine);
+            let room_sender = room_sender.clone();
+
+            thread::spawn(move || {
+                let response_room_create = game_engine.create_room("Owner", None).unwrap();
+                for i in 0..2 {
+                    game_engine.join_room(&response_room_create.room_code, &format!("Lackey {}", i)).unwrap();
+                }
+
+                let response_game_start = game_engine.start(response_room_create.room_id, response_room_create.player_id).unwrap();
+                room_sender.send(response_game_start.room_status).unwrap();
+            });
+        }
+        for _ in 0..16 {
+            assert_eq!(room_receiver.recv_timeout(Duration::from_secs(5)).unwrap(), "LEADER_OPTIONS");
+        }
+
+        assert!(waiting_receiver.try_recv().is_err());
+        drop(locked_room_guard);
+        waiting_receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        waiting.join().unwrap();
+        assert_eq!(game_engine.room_state(locked_room_id).unwrap().players.len(), 2);
+    }
+
+    #[test]
+    fn tells_whether_each_action_changed_the_room() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { phase_timers: NO_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+
+        let prompt_options = game_engine.options(room_id, player_ids[0]).unwrap();
+        assert!(!prompt_options.result.state_changed);
+        game_engine.pick(room_id, player_ids[0], prompt_options.options[0].option_id).unwrap();
+
+        // A lackey submitting does not move the room along, but still changes it
+        let finisher_id = game_engine.options(room_id, player_ids[1]).unwrap().options[0].option_id;
+        let response_game_pick = game_engine.pick(room_id, player_ids[1], finisher_id).unwrap();
+        assert_eq!(response_game_pick.room_status, "LACKEY_OPTIONS");
+        assert!(response_game_pick.state_changed);
+
+        let other_finisher_id = game_engine.options(room_id, player_ids[2]).unwrap().options[0].option_id;
+        game_engine.pick(room_id, player_ids[2], other_finisher_id).unwrap();
+        game_engine.pick(room_id, player_ids[0], finisher_id).unwrap();
+
+        assert!(game_engine.start(room_id, player_ids[1]).unwrap().state_changed);
+        let response_game_start = game_engine.start(room_id, player_ids[1]).unwrap();
+        assert_eq!(response_game_start.room_status, "ROUND_WINNER");
+        assert!(!response_game_start.state_changed);
+    }
+
+    #[test]
+    fn owner_kicks_bans_transfers_and_locks() {
+        let game_engine = new_game_engine();
+        let response_room_create = game_engine.create_room("Owner", None).unwrap();
+        let room_id = response_room_create.room_id;
+        let owner_id = response_room_create.player_id;
+        let room_code = response_room_create.room_code;
+        let kicked_id = game_engine.join_room(&room_code, "Kicked").unwrap().player_id;
+        let banned_id = game_engine.join_room(&room_code, "Banned").unwrap().player_id;
+        let lackey_id = game_engine.join_room(&room_code, "Lackey").unwrap().player_id;
+
+        assert!(matches!(game_engine.kick_player(room_id, lackey_id, kicked_id, false), Err(GameError::NotOwner(_))));
+        assert!(game_engine.kick_player(room_id, owner_id, kicked_id, false).unwrap().state_changed);
+        assert!(game_engine.kick_player(room_id, owner_id, banned_id, true).unwrap().state_changed);
+        assert_eq!(game_engine.room_state(room_id).unwrap().players.len(), 2);
+
+        // Only a ban keeps them out, in any case
+        game_engine.join_room(&room_code, "Kicked").unwrap();
+        assert!(matches!(game_engine.join_room(&room_code, "BANNED"), Err(GameError::PlayerBanned)));
+
+        assert!(game_engine.transfer_ownership(room_id, owner_id, lackey_id).unwrap().state_changed);
+        assert_eq!(game_engine.room_state(room_id).unwrap().owner_id, lackey_id);
+        assert!(matches!(game_engine.lock_room(room_id, owner_id, true), Err(GameError::NotOwner(_))));
+
+        assert!(game_engine.lock_room(room_id, lackey_id, true).unwrap().state_changed);
+        assert!(!game_engine.lock_room(room_id, lackey_id, true).unwrap().state_changed);
+        assert!(game_engine.room_state(room_id).unwrap().is_locked);
+        assert!(matches!(game_engine.join_room(&room_code, "Late"), Err(GameError::RoomLocked)));
+
+        game_engine.lock_room(room_id, lackey_id, false).unwrap();
+        game_engine.join_room(&room_code, "Late").unwrap();
+    }
+
+    #[test]
+    fn rejoins_with_the_same_score_hand_and_submission() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { phase_timers: NO_TIMERS, ..RoomSettings::default() };
+        let response_room_create = game_engine.create_room("Owner", Some(settings)).unwrap();
+        let room_id = response_room_create.room_id;
+        let room_code = response_room_create.room_code;
+        let next_leader_id = game_engine.join_room(&room_code, "Next leader").unwrap().player_id;
+        let response_room_join = game_engine.join_room(&room_code, "Lackey").unwrap();
+        let lackey_id = response_room_join.player_id;
+        let player_ids = [response_room_create.player_id, next_leader_id, lackey_id];
+
+        game_engine.start(room_id, player_ids[0]).unwrap();
+        play_round(&game_engine, room_id, &player_ids, lackey_id);
+        ready_everyone(&game_engine, room_id, &player_ids);
+
+        assert_eq!(game_engine.room_state(room_id).unwrap().leader_id, next_leader_id);
+        let prompt_options = game_engine.options(room_id, next_leader_id).unwrap();
+        game_engine.pick(room_id, next_leader_id, prompt_options.options[0].option_id).unwrap();
+        let finisher_id = game_engine.options(room_id, lackey_id).unwrap().options[0].option_id;
+        game_engine.pick(room_id, lackey_id, finisher_id).unwrap();
+
+        let player_snapshot = |game_engine: &GameEngine| {
+            let room = game_engine.room(room_id).unwrap();
+            let room = room.lock().unwrap();
+            let player = room.player(lackey_id).unwrap();
+            (player.score, player.finishers.clone(), player.submitted_finisher_id)
+        };
+        let player_before = player_snapshot(&game_engine);
+        assert_eq!(player_before.0, 1);
+        assert_eq!(player_before.2, Some(finisher_id));
+
+        let response_room_rejoin = game_engine.rejoin_room(&room_code.to_lowercase(), &response_room_join.reconnect_token).unwrap();
+        assert_eq!(response_room_rejoin.player_id, lackey_id);
+        assert_eq!(response_room_rejoin.player_name, "Lackey");
+        assert!(matches!(game_engine.authenticate(room_id, lackey_id, &response_room_join.player_token), Err(GameError::NotAuthenticated)));
+        game_engine.authenticate(room_id, lackey_id, &response_room_rejoin.player_token).unwrap();
+        assert_eq!(player_snapshot(&game_engine), player_before);
+
+        // Only the reconnect token of a player in that same room works
+        let other_room = game_engine.create_room("Stranger", None).unwrap();
+        assert!(matches!(game_engine.rejoin_room(&room_code, ""), Err(GameError::InvalidData)));
+        assert!(matches!(game_engine.rejoin_room(&room_code, &other_room.reconnect_token), Err(GameError::NotAuthenticated)));
+        assert!(matches!(game_engine.rejoin_room(&room_code, &response_room_rejoin.player_token), Err(GameError::NotAuthenticated)));
+    }
+
+    #[test]
+    fn rejects_actions_out_of_turn() {
+        let game_engine = new_game_engine();
+        let settings = RoomSettings { phase_timers: NO_TIMERS, ..RoomSettings::default() };
+        let (room_id, player_ids) = new_game(&game_engine, settings, 3);
+
+        assert_eq!(game_engine.options(room_id, player_ids[1]).unwrap_err(), GameError::WrongRoomStatus(RoomStatus::LeaderOptions));
+        assert_eq!(game_engine.pick(room_id, player_ids[0], u16::MAX).unwrap_err(), GameError::OptionNotFound(u16::MAX));
+        assert_eq!(game_engine.start(room_id, player_ids[0]).unwrap_err(), GameError::WrongRoomStatus(RoomStatus::LeaderOptions));
+    }
+}
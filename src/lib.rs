@@ -0,0 +1,9 @@
+pub mod deck;
+pub mod error;
+pub mod events;
+pub mod filter;
+pub mod game;
+pub mod names;
+pub mod persistence;
+pub mod protocol;
+pub mod websocket;
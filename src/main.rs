@@ -1,44 +1,70 @@
-use std::collections::HashMap;
-use std::fmt;
 use std::fs;
-use std::io::{self, BufReader};
-use std::option::Option;
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
 use std::str;
-use std::time::Instant;
-
-use rand::Rng;
-use rand::distributions::{Alphanumeric, DistString};
-use rand::rngs::ThreadRng;
-
-use dotenv;
-
-use serde::{Deserialize, Serialize};
-use tiny_http::{Header, HeaderField, Method, Response, Server, StatusCode};
-
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tiny_http::{Header, HeaderField, Method, Request, Response, Server, StatusCode};
+use tungstenite::WebSocket;
+
+use se_pelo::deck::{Deck, DeckKind};
+use se_pelo::error::GameError;
+use se_pelo::events::RoomEvents;
+use se_pelo::filter::{FilterAction, WordFilter};
+use se_pelo::game::{DuplicateNames, GameEngine};
+use se_pelo::persistence::FileRoomStore;
+use se_pelo::websocket::RoomSockets;
+use se_pelo::protocol::{
+    RequestGameForceAdvance, RequestGameOptions, RequestGamePick, RequestGameStart, RequestRoomCheck, RequestRoomCreate, RequestRoomJoin, RequestRoomKick,
+    RequestRoomLeave, RequestRoomLock, RequestRoomRejoin, RequestRoomSettings, RequestRoomTransfer
+};
+
+
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+// How long a WebSocket message can wait before being handled
+const SOCKET_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// So that connections that never finish the handshake do not keep their thread forever
+const SOCKET_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+const PHASE_TIMER_INTERVAL: Duration = Duration::from_secs(1);
 
 fn main() {
     let _ = dotenv::dotenv();
 
-    //TODO: is there a smarter way to read line by line things?
+    // Either .toml decks or plain text, see Deck
     let prompts_path = std::env::var("PROMPTS_PATH")
         .expect("Provide a path to read the Prompts");
-    let prompts_string = fs::read_to_string(prompts_path.clone())
-        .expect(&format!("Prompts were not found in path '{prompts_path}'"));
-    let prompts: Vec<String> = prompts_string.lines().map(String::from).collect();
-    //^ How about using an array instead? size is not meant to change
+    let prompts_deck = Deck::load(&prompts_path, DeckKind::Prompts)
+        .unwrap_or_else(|error| panic!("Invalid Prompts in path '{prompts_path}', {error}"));
+    println!("Loaded {} prompts from pack '{}'.", prompts_deck.cards.len(), prompts_deck.pack.name);
 
     let finishers_path = std::env::var("FINISHERS_PATH")
         .expect("Provide a path to read the Finishers");
-    let finishers_string = fs::read_to_string(finishers_path.clone())
-        .expect(&format!("Finishers were not found in path '{finishers_path}'"));
-    let finishers: Vec<String> = finishers_string.lines().map(String::from).collect();
-    //^ How about using an array instead? size is not meant to change
-
-    let prompt_ids_usize: Vec<usize> = (0..prompts.len()).collect();
-    let prompt_ids: Vec<u16> = prompt_ids_usize.iter().map(|&x| x as u16).collect();
-
-    let finisher_ids_usize: Vec<usize> = (0..finishers.len()).collect();
-    let finisher_ids: Vec<u16> = finisher_ids_usize.iter().map(|&x| x as u16).collect();
+    let finishers_deck = Deck::load(&finishers_path, DeckKind::Finishers)
+        .unwrap_or_else(|error| panic!("Invalid Finishers in path '{finishers_path}', {error}"));
+    println!("Loaded {} finishers from pack '{}'.", finishers_deck.cards.len(), finishers_deck.pack.name);
+
+    // Optional, one word per line
+    let blocked_words: Option<Vec<String>> = std::env::var("BLOCKED_WORDS_PATH").ok().map(|blocked_words_path| {
+        let blocked_words_string = fs::read_to_string(blocked_words_path.clone())
+            .unwrap_or_else(|_| panic!("Blocked words were not found in path '{blocked_words_path}'"));
+        blocked_words_string.lines().map(String::from).collect()
+    });
+
+    // "reject" or "mask"
+    let blocked_words_action = match std::env::var("BLOCKED_WORDS_ACTION") {
+        Ok(a) => match a.as_str() {
+            "reject" => FilterAction::Reject,
+            "mask" => FilterAction::Mask,
+            _ => panic!("Unknown BLOCKED_WORDS_ACTION '{a}', use 'reject' or 'mask'")
+        },
+        Err(..) => FilterAction::Reject,
+    };
 
     let host = match std::env::var("HOST") {
         Ok(p) => p,
@@ -50,6 +76,52 @@ fn main() {
         Err(..) => 8000,
     };
 
+    let worker_count = match std::env::var("WORKERS") {
+        Ok(w) => w.parse::<usize>().unwrap(),
+        Err(..) => thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+    };
+
+    // A listener of its own, since the HTTP one cant hand over non-blocking sockets. Deployments have to expose both ports
+    let websocket_port = match std::env::var("WEBSOCKET_PORT") {
+        Ok(p) => p.parse::<u16>().unwrap(),
+        Err(..) => 8001,
+    };
+
+    // In seconds since the last RoomCheck
+    let player_disconnect_timeout = match std::env::var("PLAYER_DISCONNECT_TIMEOUT") {
+        Ok(t) => Duration::from_secs(t.parse::<u64>().unwrap()),
+        Err(..) => Duration::from_secs(30),
+    };
+
+    let player_remove_timeout = match std::env::var("PLAYER_REMOVE_TIMEOUT") {
+        Ok(t) => Duration::from_secs(t.parse::<u64>().unwrap()),
+        Err(..) => Duration::from_secs(300),
+    };
+
+    let room_idle_timeout = match std::env::var("ROOM_IDLE_TIMEOUT") {
+        Ok(t) => Duration::from_secs(t.parse::<u64>().unwrap()),
+        Err(..) => Duration::from_secs(120),
+    };
+
+    // "reject" or "suffix"
+    let duplicate_names = match std::env::var("DUPLICATE_PLAYER_NAMES") {
+        Ok(d) => match d.as_str() {
+            "reject" => DuplicateNames::Reject,
+            "suffix" => DuplicateNames::Suffix,
+            _ => panic!("Unknown DUPLICATE_PLAYER_NAMES '{d}', use 'reject' or 'suffix'")
+        },
+        Err(..) => DuplicateNames::Reject,
+    };
+
+    // Without it, nothing is saved and every restart starts from scratch
+    let snapshot_path = std::env::var("SNAPSHOT_PATH").ok();
+
+    // In seconds, how much can be lost on a crash. Stopping the server (SIGINT or SIGTERM) saves right away
+    let snapshot_interval = match std::env::var("SNAPSHOT_INTERVAL") {
+        Ok(i) => Duration::from_secs(i.parse::<u64>().unwrap()),
+        Err(..) => Duration::from_secs(10),
+    };
+
     // Server (TPC bind) errors not handled for simplicity
     let host_port = format!("{}:{}", host, port);
 
@@ -57,1345 +129,549 @@ fn main() {
     println!("Starting server at {}.", host_port);
     let server = Server::http(host_port).unwrap();
 
+    let websocket_host_port = format!("{}:{}", host, websocket_port);
+    println!("Starting WebSocket server at {}.", websocket_host_port);
+    let websocket_listener = TcpListener::bind(websocket_host_port).unwrap();
+    let (websocket_sender, websocket_receiver) = mpsc::channel();
+    thread::spawn(move || accept_websockets(websocket_listener, websocket_sender));
 
-    let mut rng = rand::thread_rng();
-    let mut rooms: HashMap<u32, Room> = HashMap::new();
-    let mut players: HashMap<u32, Player> = HashMap::new();
-    let mut room_players: HashMap<u32, Vec<u32>> = HashMap::new();
-    let mut room_prompts: HashMap<u32, Vec<u16>> = HashMap::new();
-    let mut room_finishers: HashMap<u32, HashMap<u32, u16>> = HashMap::new();
-    let mut player_finishers: HashMap<u32, Vec<u16>> = HashMap::new();
-    let mut room_available_prompts: HashMap<u32, Vec<u16>> = HashMap::new();
-    let mut room_available_finishers: HashMap<u32, Vec<u16>> = HashMap::new();
-    let mut room_players_not_ready: HashMap<u32, Vec<u32>> = HashMap::new();
-
-    //TODO: is the mutable Game Context stuff thread-safe?
-    let game_context = GameContext {
-        rng: &mut rng,
-        rooms: &mut rooms,
-        players: &mut players,
-        room_players: &mut room_players,
-        room_prompts: &mut room_prompts,
-        room_finishers: &mut room_finishers,
-        player_finishers: &mut player_finishers,
-        room_available_prompts: &mut room_available_prompts,
-        room_available_finishers: &mut room_available_finishers,
-        room_players_not_ready: &mut room_players_not_ready
-    };
+    let mut game_engine = GameEngine::new(prompts_deck.texts(), finishers_deck.texts()).with_duplicate_names(duplicate_names);
+    if let Some(blocked_words) = blocked_words {
+        game_engine = game_engine.with_word_filter(WordFilter::new(&blocked_words, blocked_words_action));
+    }
+    let game_engine = Arc::new(game_engine);
 
+    if let Some(snapshot_path) = snapshot_path {
+        let room_store = FileRoomStore::new(snapshot_path);
+        match game_engine.restore(&room_store) {
+            Ok(restored_room_count) => println!("Restored {} rooms.", restored_room_count),
+            Err(error) => panic!("Could not restore the rooms: {}", error)
+        }
+        // Both savers write through the same temporary file, so only one at a time
+        let room_store = Arc::new(Mutex::new(room_store));
+
+        let snapshot_game_engine = Arc::clone(&game_engine);
+        let snapshot_room_store = Arc::clone(&room_store);
+        thread::spawn(move || loop {
+            thread::sleep(snapshot_interval);
+            if let Err(error) = snapshot_game_engine.save(&*snapshot_room_store.lock().unwrap()) {
+                println!("Snapshot - Could not save the rooms: {}", error);
+            }
+        });
+
+        let shutdown_game_engine = Arc::clone(&game_engine);
+        ctrlc::set_handler(move || {
+            match shutdown_game_engine.save(&*room_store.lock().unwrap()) {
+                Ok(()) => println!("Shutting down, rooms saved."),
+                Err(error) => println!("Shutting down, could not save the rooms: {}", error)
+            }
+            std::process::exit(0);
+        }).unwrap();
+    }
+    let room_events = Arc::new(Mutex::new(RoomEvents::new()));
+    let room_sockets = Arc::new(Mutex::new(RoomSockets::new()));
+
+    // Idle event streams get dropped by proxies, so they need to hear from us every now and then.
+    // Players with an open stream or socket do not need to RoomCheck, so it counts as one for them.
+    let keep_alive_game_engine = Arc::clone(&game_engine);
+    let keep_alive_room_events = Arc::clone(&room_events);
+    let keep_alive_room_sockets = Arc::clone(&room_sockets);
+    thread::spawn(move || loop {
+        thread::sleep(KEEP_ALIVE_INTERVAL);
+        let mut sessions = {
+            let mut room_events = keep_alive_room_events.lock().unwrap();
+            room_events.keep_alive();
+            room_events.sessions()
+        };
+        sessions.append(&mut keep_alive_room_sockets.lock().unwrap().sessions());
+        for (room_id, player_id) in sessions {
+            let _ = keep_alive_game_engine.touch(room_id, player_id);
+        }
+    });
+
+    let timer_game_engine = Arc::clone(&game_engine);
+    let timer_room_events = Arc::clone(&room_events);
+    let timer_room_sockets = Arc::clone(&room_sockets);
+    thread::spawn(move || loop {
+        thread::sleep(PHASE_TIMER_INTERVAL);
+        for changed_room_id in timer_game_engine.expire_phases() {
+            publish_room_state(&timer_game_engine, &timer_room_events, &timer_room_sockets, changed_room_id);
+        }
+    });
+
+    let reaper_game_engine = Arc::clone(&game_engine);
+    let reaper_room_events = Arc::clone(&room_events);
+    let reaper_room_sockets = Arc::clone(&room_sockets);
+    thread::spawn(move || loop {
+        thread::sleep(REAP_INTERVAL);
+        let (changed_room_ids, removed_players) = reaper_game_engine.reap_idle_players(player_disconnect_timeout, player_remove_timeout);
+        for (room_id, player_id) in removed_players {
+            close_player(&reaper_room_events, &reaper_room_sockets, room_id, player_id);
+        }
+        for changed_room_id in changed_room_ids {
+            publish_room_state(&reaper_game_engine, &reaper_room_events, &reaper_room_sockets, changed_room_id);
+        }
+        for removed_room_id in reaper_game_engine.reap_idle_rooms(room_idle_timeout) {
+            reaper_room_events.lock().unwrap().close_room(removed_room_id);
+            reaper_room_sockets.lock().unwrap().close_room(removed_room_id);
+        }
+    });
+
+    let socket_game_engine = Arc::clone(&game_engine);
+    let socket_room_events = Arc::clone(&room_events);
+    let socket_room_sockets = Arc::clone(&room_sockets);
+    thread::spawn(move || loop {
+        // Wakes up as soon as a new WebSocket arrives, or after a while to poll the existing ones
+        if let Ok(websocket) = websocket_receiver.recv_timeout(SOCKET_POLL_INTERVAL) {
+            socket_room_sockets.lock().unwrap().add(websocket);
+        }
 
-    //TODO: multiple workers
-    for mut request in server.incoming_requests() {
-        // This can be quite noisy with all the RoomCheck requests:
-        // println!("received request! method: {:?}, url: {:?}", request.method(), request.url());
-
-        // Logging Headers is noisy:
-        // println!("received request! method: {:?}, url: {:?}, headers: {:?}",
-        //     request.method(),
-        //     request.url(),
-        //     request.headers()
-        // );
-
-        match get_game_action(request.method(), request.url()) {
-            Some(game_action) => {
-
-                // Hack for development
-                // Could the headers be a constant?
-                let access_control_allow_headers = Header::from_bytes(b"Access-Control-Allow-Headers", b"*").unwrap();
-                let access_control_allow_origin_header = Header::from_bytes(b"Access-Control-Allow-Origin", b"*").unwrap();
-                let access_control_allow_methods = Header::from_bytes(b"Access-Control-Allow-Methods", b"GET, POST").unwrap();
-                let access_control_allow_max_age = Header::from_bytes(b"Access-Control-Max-Age", b"3600").unwrap(); // 3600 = 1 hour
-                let headers = Vec::from([access_control_allow_headers, access_control_allow_origin_header, access_control_allow_methods, access_control_allow_max_age]);
-
-                match game_action {
-                    GameAction::CorsOption => {
-                        let response = Response::new(StatusCode(200), headers, io::empty(), None, None);
-                        request.respond(response).unwrap();
-                    },
-                    GameAction::RoomCreate => {
-                        println!("RoomCreate request!");
-
-                        //TODO: I know the code is reapeating A LOT, but got to learn how to use Rust's ownership before I can start doing refactoring things properly
-                        // Could the HeaderField be a constant?
-                        let content_type_header_field = HeaderField::from_bytes(b"Content-Type").unwrap();
-                        let content_type_found = request.headers().iter().find(|&h| h.field == content_type_header_field);
-                        if content_type_found.is_none() || (content_type_found.unwrap().value != "application/json" && content_type_found.unwrap().value != "application/json; charset=UTF-8") {
-                            println!("RoomCreate - Bad headers");
-
-                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                            request.respond(response).unwrap();
-                        } else {
-
-                            let mut content = String::new();
-                            let reader = request.as_reader();
-                            match reader.read_to_string(&mut content) {
-                                Ok(_) => {
-
-                                    match serde_json::from_str::<RequestRoomCreate>(&mut content) {
-                                        Ok(deserialized_request) => {
-
-                                            let owner_name = deserialized_request.owner_name;
-                                            let trimmed_owner_name = owner_name.trim();
-
-                                            if trimmed_owner_name.is_empty() || trimmed_owner_name.len() > 16 {
-                                                println!("RoomCreate - Invalid data");
-
-                                                let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                request.respond(response).unwrap();
-                                            } else {
-
-                                                //TODO: Need thread safe id/code generator that doesn't repeat values...
-                                                let player_id = game_context.rng.r#gen();
-                                                let player = Player {
-                                                    id: player_id,
-                                                    name: trimmed_owner_name.to_string(),
-                                                    score: 0,
-                                                    last_check: Instant::now()
-                                                };
-                                                game_context.players.insert(player_id, player);
-
-                                                let room_id: u32 = game_context.rng.r#gen();
-                                                let room_code = Alphanumeric.sample_string(game_context.rng, 6).to_uppercase();
-                                                let room_code_for_response = room_code.clone();
-
-                                                let room = Room{
-                                                    id: room_id,
-                                                    code: room_code,
-                                                    room_status: RoomStatus::Waiting,
-                                                    owner_id: player_id,
-                                                    leader_id: player_id,
-                                                    round_counter: 1,
-                                                    round_total: 10, //TODO: Make Configurable
-                                                    leader_player_position: 0,
-                                                    selected_prompt_id: None,
-                                                    winner_player_id: None,
-                                                    winner_finisher_id: None
-                                                };
-                                                game_context.rooms.insert(room_id, room);
-
-                                                game_context.room_players.insert(room_id, vec![player_id]);
-
-                                                game_context.room_prompts.insert(room_id, vec![]);
-                                                game_context.room_finishers.insert(room_id, HashMap::new());
-
-                                                game_context.room_available_prompts.insert(room_id, vec![]);
-
-                                                game_context.room_available_finishers.insert(room_id, vec![]);
-
-                                                game_context.player_finishers.insert(player_id, vec![]);
-
-                                                game_context.room_players_not_ready.insert(room_id, vec![]);
-
-                                                let response_room_create = ResponseRoomCreate { room_id: room_id, room_code: room_code_for_response, player_id: player_id };
-                                                let serialized_response = serde_json::to_string(&response_room_create).unwrap();
-                                                let response_reader = BufReader::new(serialized_response.as_bytes());
-                                                let response = Response::new(StatusCode(201), headers, response_reader, Some(serialized_response.len()), None);
-                                                request.respond(response).unwrap();
-                                            }
-
-                                        },
-                                        Err(_) => {
-                                            println!("RoomCreate - Cant read JSON");
-
-                                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                            request.respond(response).unwrap();
-                                        }
-                                    };
-
-                                },
-                                Err(_) => {
-                                    println!("RoomCreate - Cant read request content");
-
-                                    let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                    request.respond(response).unwrap();
-                                }
-                            }
-                        }
-                    },
-                    GameAction::RoomJoin => {
-                        println!("RoomJoin request!");
-
-                        // Could the HeaderField be a constant?
-                        let content_type_header_field = HeaderField::from_bytes(b"Content-Type").unwrap();
-                        let content_type_found = request.headers().iter().find(|&h| h.field == content_type_header_field);
-                        if content_type_found.is_none() || content_type_found.unwrap().value != "application/json; charset=UTF-8" {
-                            println!("RoomJoin - Bad headers");
-
-                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                            request.respond(response).unwrap();
-                        } else {
-
-                            let mut content = String::new();
-                            let reader = request.as_reader();
-                            match reader.read_to_string(&mut content) {
-                                Ok(_) => {
-
-                                    match serde_json::from_str::<RequestRoomJoin>(&mut content) {
-                                        Ok(deserialized_request) => {
-
-                                            let player_name = deserialized_request.player_name;
-                                            let trimmed_player_name = player_name.trim();
-
-                                            let room_code = deserialized_request.room_code;
-                                            let trimmed_room_code = room_code.trim();
-
-                                            if trimmed_player_name.is_empty() || trimmed_player_name.len() > 16 || trimmed_room_code.is_empty() || trimmed_room_code.len() > 6 {
-                                                println!("RoomJoin - Invalid data");
-
-                                                let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                request.respond(response).unwrap();
-                                            } else {
-
-                                                let room_found = game_context.rooms.values().find(|&r| r.code == trimmed_room_code);
-                                                if room_found.is_none() {
-                                                    println!("RoomJoin - Room '{}' not found", trimmed_room_code);
-
-                                                    let response = Response::new(StatusCode(404), headers, io::empty(), None, None);
-                                                    request.respond(response).unwrap();
-                                                } else {
-                                                    let room_id = room_found.unwrap().id;
-
-                                                    //TODO: Join player: check player name is not already in the Room
-                                                    //TODO: Validate the room is in the context, otherwise 500
-                                                    //TODO: Validate max number of players
-                                                    let players_in_room = game_context.room_players.get_mut(&room_id).unwrap();
-
-
-                                                    //TODO: Need thread safe id/code generator that doesn't repeat values...
-                                                    let player_id = game_context.rng.r#gen();
-                                                    let player = Player {
-                                                        id: player_id,
-                                                        name: trimmed_player_name.to_string(),
-                                                        score: 0,
-                                                        last_check: Instant::now()
-                                                    };
-                                                    game_context.players.insert(player_id, player);
-
-                                                    game_context.player_finishers.insert(player_id, vec![]);
-
-                                                    players_in_room.push(player_id);
-
-                                                    let response_room_join = ResponseRoomJoin { room_id: room_id, player_id: player_id };
-                                                    let serialized_response = serde_json::to_string(&response_room_join).unwrap();
-                                                    let response_reader = BufReader::new(serialized_response.as_bytes());
-                                                    let response = Response::new(StatusCode(200), headers, response_reader, Some(serialized_response.len()), None);
-                                                    request.respond(response).unwrap();
-                                                }
-                                            }
-
-                                        },
-                                        Err(_) => {
-                                            println!("RoomJoin - Cant read JSON");
-
-                                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                            request.respond(response).unwrap();
-                                        }
-                                    };
-
-                                },
-                                Err(_) => {
-                                    println!("RoomJoin - Cant read request content");
-
-                                    let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                    request.respond(response).unwrap();
-                                }
-                            }
-                        }
-                    },
-                    GameAction::RoomCheck => {
-                        // println!("RoomCheck request!");
-
-                        // Could the HeaderField be a constant?
-                        let content_type_header_field = HeaderField::from_bytes(b"Content-Type").unwrap();
-                        let content_type_found = request.headers().iter().find(|&h| h.field == content_type_header_field);
-                        if content_type_found.is_none() || content_type_found.unwrap().value != "application/json; charset=UTF-8" {
-                            println!("RoomCheck - Bad headers");
-
-                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                            request.respond(response).unwrap();
-                        } else {
-
-                            let mut content = String::new();
-                            let reader = request.as_reader();
-                            match reader.read_to_string(&mut content) {
-                                Ok(_) => {
-
-                                    match serde_json::from_str::<RequestRoomCheck>(&mut content) {
-                                        Ok(deserialized_request) => {
-
-                                            let room_id = deserialized_request.room_id;
-
-                                            let player_id = deserialized_request.player_id;
-
-                                            if room_id == 0 || player_id == 0 {
-                                                println!("RoomCheck - Invalid data");
-
-                                                let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                request.respond(response).unwrap();
-                                            } else {
-
-                                                let room_found = game_context.rooms.get(&room_id);
-                                                if room_found.is_none() {
-                                                    println!("RoomCheck - Room {} not found", room_id);
-
-                                                    let response = Response::new(StatusCode(404), headers, io::empty(), None, None);
-                                                    request.respond(response).unwrap();
-                                                } else {
-                                                    let players_in_room = game_context.room_players.get(&room_id).unwrap();
-
-                                                    let player_found = players_in_room.iter().find(|&p_id| p_id == &player_id);
-                                                    if player_found.is_none() {
-                                                        println!("RoomCheck - Player {} not found in room {}", player_id, room_id);
-
-                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                        request.respond(response).unwrap();
-                                                    } else {
-                                                        let player_optional = game_context.players.get_mut(&player_id);
-                                                        if player_optional.is_none() {
-                                                            println!("RoomCheck - Player {} not found!!!", player_id);
-
-                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                            request.respond(response).unwrap();
-                                                        } else {
-                                                            let room = room_found.unwrap();
-
-                                                            let player = player_optional.unwrap();
-                                                            player.last_check = Instant::now();
-
-                                                            let room_players_not_ready = game_context.room_players_not_ready.get(&room_id).unwrap();
-
-                                                            let players_in_room_response = players_in_room.iter().map(|room_player_id| {
-                                                                //TODO: Validate the user is in the context.users(?), otherwise 500
-                                                                let room_player = game_context.players.get(&room_player_id).unwrap();
-                                                                let is_finisher_ready: Option<bool>;
-                                                                if room.leader_id == room_player.id {
-                                                                    is_finisher_ready = None;
-                                                                } else {
-                                                                    let room_finishers = game_context.room_finishers.get(&room_id).unwrap();
-                                                                    if room_finishers.get(room_player_id).is_none() {
-                                                                        is_finisher_ready = Some(false);
-                                                                    } else {
-                                                                        is_finisher_ready = Some(true);
-                                                                    }
-                                                                }
-
-                                                                let is_player_next_round_ready: Option<bool>;
-                                                                match &room.room_status {
-                                                                    RoomStatus::RoundWinner => {
-                                                                        is_player_next_round_ready = Some(!room_players_not_ready.contains(&room_player_id));
-                                                                    },
-                                                                    _ => {
-                                                                        is_player_next_round_ready = None;
-                                                                    }
-                                                                }
-
-                                                                ResponseRoomCheckPlayer {
-                                                                    player_id: room_player.id,
-                                                                    player_name: room_player.name.to_string(),
-                                                                    score: room_player.score,
-                                                                    is_finisher_ready: is_finisher_ready,
-                                                                    is_next_round_ready: is_player_next_round_ready,
-                                                                    last_check: u16::try_from(room_player.last_check.elapsed().as_secs()).unwrap()
-                                                                }
-                                                            }).collect();
-
-                                                            let room_status = room.room_status.to_string();
-
-                                                            let response_prompt_text: Option<String>;
-                                                            let response_finishers: Option<Vec<ResponseRoomCheckFinisher>>;
-                                                            match &room.room_status {
-                                                                RoomStatus::LackeyOptions => {
-                                                                    let prompt_position = room.selected_prompt_id.unwrap();
-                                                                    let prompt_position_usize = usize::try_from(prompt_position).unwrap();
-                                                                    response_prompt_text = Some(prompts[prompt_position_usize].clone());
-
-                                                                    response_finishers = None;
-                                                                },
-                                                                RoomStatus::LeaderPick => {
-                                                                    let prompt_position = room.selected_prompt_id.unwrap();
-                                                                    let prompt_position_usize = usize::try_from(prompt_position).unwrap();
-                                                                    response_prompt_text = Some(prompts[prompt_position_usize].clone());
-
-                                                                    response_finishers = None;
-                                                                },
-                                                                RoomStatus::RoundWinner => {
-                                                                    let prompt_position = room.selected_prompt_id.unwrap();
-                                                                    let prompt_position_usize = usize::try_from(prompt_position).unwrap();
-                                                                    response_prompt_text = Some(prompts[prompt_position_usize].clone());
-
-
-                                                                    let room_finishers = game_context.room_finishers.get(&room_id).unwrap();
-                                                                    let converted_finishers = room_finishers.iter().map(|(&player_id, &finisher_id)| {
-
-                                                                        let finisher_position_usize = usize::try_from(finisher_id).unwrap();
-                                                                        let finisher = &finishers[finisher_position_usize];
-
-                                                                        let player = game_context.players.get(&player_id).unwrap();
-
-                                                                        ResponseRoomCheckFinisher {
-                                                                            player_name: player.name.clone(),
-                                                                            finisher_text: finisher.to_string(),
-                                                                            is_winner: (player_id == room.winner_player_id.unwrap())
-                                                                        }
-                                                                    }).collect();
-
-                                                                    response_finishers = Some(converted_finishers);
-                                                                },
-                                                                _ => {
-                                                                    response_prompt_text = None;
-                                                                    response_finishers = None;
-                                                                }
-                                                            }
-
-
-                                                            let response_room_create = ResponseRoomCheck {
-                                                                players: players_in_room_response,
-                                                                room_status: room_status,
-                                                                owner_id: room.owner_id,
-                                                                leader_id: room.leader_id,
-                                                                round_counter: room.round_counter,
-                                                                round_total: room.round_total,
-                                                                prompt_text: response_prompt_text,
-                                                                finishers: response_finishers
-                                                            };
-                                                            let serialized_response = serde_json::to_string(&response_room_create).unwrap();
-                                                            let response_reader = BufReader::new(serialized_response.as_bytes());
-                                                            let response = Response::new(StatusCode(201), headers, response_reader, Some(serialized_response.len()), None);
-                                                            request.respond(response).unwrap();
-                                                        }
-                                                    }
-                                                }
-                                            }
-
-                                        },
-                                        Err(_) => {
-                                            println!("RoomCheck - Cant read JSON");
-
-                                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                            request.respond(response).unwrap();
-                                        }
-                                    };
-
-                                },
-                                Err(_) => {
-                                    println!("RoomCheck - Cant read request content");
-
-                                    let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                    request.respond(response).unwrap();
-                                }
-                            }
-                        }
-                    },
-                    GameAction::GameStart => {
-                        println!("GameStart request!");
-
-                        // Could the HeaderField be a constant?
-                        let content_type_header_field = HeaderField::from_bytes(b"Content-Type").unwrap();
-                        let content_type_found = request.headers().iter().find(|&h| h.field == content_type_header_field);
-                        if content_type_found.is_none() || content_type_found.unwrap().value != "application/json; charset=UTF-8" {
-                            println!("GameStart - Bad headers");
-
-                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                            request.respond(response).unwrap();
-                        } else {
-
-                            let mut content = String::new();
-                            let reader = request.as_reader();
-                            match reader.read_to_string(&mut content) {
-                                Ok(_) => {
-
-                                    match serde_json::from_str::<RequestGameStart>(&mut content) {
-                                        Ok(deserialized_request) => {
-
-                                            let room_id = deserialized_request.room_id;
-
-                                            let player_id = deserialized_request.player_id;
-
-                                            if room_id == 0 || player_id == 0 {
-                                                println!("GameStart - Invalid data");
-
-                                                let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                request.respond(response).unwrap();
-                                            } else {
-
-                                                let room_found = game_context.rooms.get_mut(&room_id);
-                                                if room_found.is_none() {
-                                                    println!("GameStart - Room {} not found", room_id);
-                                                    let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                    request.respond(response).unwrap();
-                                                } else {
-
-                                                    let players_in_room = game_context.room_players.get(&room_id).unwrap();
-                                                    let room_player_count = u8::try_from(players_in_room.len()).unwrap();
-                                                    if room_player_count <= 2 {
-                                                        println!("GameStart - Not enough players in room ({})", room_player_count);
-
-                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                        request.respond(response).unwrap();
-                                                    } else {
-                                                        let player_found = players_in_room.iter().find(|&p_id| p_id == &player_id);
-                                                        if player_found.is_none() {
-                                                            println!("GameStart - Player {} not found in room {}", player_id, room_id);
-
-                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                            request.respond(response).unwrap();
-                                                        } else {
-                                                            let player_optional = game_context.players.get(&player_id);
-                                                            if player_optional.is_none() {
-                                                                println!("GameStart - Player {} not found!!!", player_id);
-
-                                                                let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                request.respond(response).unwrap();
-                                                            } else {
-                                                                let room = room_found.unwrap();
-
-                                                                match room.room_status {
-                                                                    RoomStatus::Waiting => {
-                                                                        if player_id != room.owner_id {
-                                                                            println!("GameStart - Player {} is not the owner of the room", player_id);
-
-                                                                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        } else {
-                                                                            // New game and round
-                                                                            room.room_status = RoomStatus::LeaderOptions;
-
-                                                                            // No need to set the round_counter or leader_player_position.
-                                                                            // The default values with which the Room was created are fine.
-
-                                                                            let response = Response::new(StatusCode(204), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        }
-                                                                    },
-                                                                    RoomStatus::RoundWinner => {
-                                                                        // Old game but new round
-
-                                                                        let room_players_not_ready_found = game_context.room_players_not_ready.get_mut(&room_id);
-                                                                        if room_players_not_ready_found.is_none() {
-                                                                            println!("GameStart - Room {} of Players Ready not found", room_id);
-
-                                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        } else {
-                                                                            let room_players_not_ready = room_players_not_ready_found.unwrap();
-                                                                            room_players_not_ready.retain(|&p_id| p_id != player_id);
-
-                                                                            if room_players_not_ready.is_empty() {
-                                                                                // All players are ready
-
-                                                                                room.selected_prompt_id = None;
-                                                                                room.winner_player_id = None;
-                                                                                room.winner_finisher_id = None;
-
-                                                                                if room.round_counter < room.round_total {
-                                                                                    // Next round
-
-                                                                                    // Clear Prompts
-                                                                                    game_context.room_prompts.get_mut(&room_id).unwrap().clear();
-
-                                                                                    room.round_counter += 1;
-                                                                                    room.leader_player_position = (room.leader_player_position + 1) % room_player_count;
-
-                                                                                    let player_position_usize = usize::try_from(room.leader_player_position).unwrap();
-                                                                                    room.leader_id = players_in_room[player_position_usize];
-
-                                                                                    room.room_status = RoomStatus::LeaderOptions;
-
-                                                                                    let response = Response::new(StatusCode(204), headers, io::empty(), None, None);
-                                                                                    request.respond(response).unwrap();
-                                                                                } else {
-                                                                                    // Game end
-
-                                                                                    // Leader and turn changes happen later during GameWinner.
-
-                                                                                    room.room_status = RoomStatus::GameWinner;
-
-                                                                                    let response = Response::new(StatusCode(204), headers, io::empty(), None, None);
-                                                                                    request.respond(response).unwrap();
-                                                                                }
-
-                                                                            } else {
-                                                                                // Not all players are ready
-
-                                                                                let response = Response::new(StatusCode(204), headers, io::empty(), None, None);
-                                                                                request.respond(response).unwrap();
-                                                                            }
-                                                                        }
-                                                                    },
-                                                                    RoomStatus::GameWinner => {
-                                                                        // Start a new Game
-
-                                                                        if player_id != room.owner_id {
-                                                                            println!("GameStart - Player {} is not the owner of the room", player_id);
-
-                                                                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        } else {
-
-                                                                            // Reset all the player scores
-                                                                            let mut reset_ok = true;
-                                                                            for room_player_id in players_in_room {
-                                                                                let room_player_optional = game_context.players.get_mut(&room_player_id);
-                                                                                if room_player_optional.is_none() {
-                                                                                    println!("GameStart - Player {} not found!!!", room_player_id);
-
-                                                                                    reset_ok = false;
-                                                                                    break;
-                                                                                } else {
-                                                                                    let room_player = room_player_optional.unwrap();
-                                                                                    room_player.score = 0;
-                                                                                }
-                                                                            }
-
-                                                                            if reset_ok {
-                                                                                // Clear Prompts
-                                                                                game_context.room_prompts.get_mut(&room_id).unwrap().clear();
-
-                                                                                room.leader_player_position = (room.leader_player_position + 1) % room_player_count;
-
-                                                                                let player_position_usize = usize::try_from(room.leader_player_position).unwrap();
-                                                                                room.leader_id = players_in_room[player_position_usize];
-
-                                                                                room.room_status = RoomStatus::LeaderOptions;
-                                                                                room.round_counter = 1;
-                                                                                room.selected_prompt_id = None;
-                                                                                room.winner_player_id = None;
-                                                                                room.winner_finisher_id = None;
-
-                                                                                let response = Response::new(StatusCode(204), headers, io::empty(), None, None);
-                                                                                request.respond(response).unwrap();
-                                                                            } else {
-                                                                                println!("GameStart - Reset failed!!!");
-                                                                                let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                                request.respond(response).unwrap();
-                                                                            }
-                                                                        }
-                                                                    }
-                                                                    _ => {
-                                                                        println!("GameStart - Room not waiting");
-                                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                        request.respond(response).unwrap();
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-
-                                        },
-                                        Err(_) => {
-                                            println!("GameStart - Cant read JSON");
-
-                                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                            request.respond(response).unwrap();
-                                        }
-                                    };
-
-                                },
-                                Err(_) => {
-                                    println!("GameStart - Cant read request content");
-
-                                    let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                    request.respond(response).unwrap();
-                                }
-                            }
-                        }
-                    },
-                    GameAction::GameOptions => {
-                        println!("GameOptions request!");
-
-                        // Could the HeaderField be a constant?
-                        let content_type_header_field = HeaderField::from_bytes(b"Content-Type").unwrap();
-                        let content_type_found = request.headers().iter().find(|&h| h.field == content_type_header_field);
-                        if content_type_found.is_none() || content_type_found.unwrap().value != "application/json; charset=UTF-8" {
-                            println!("GameOptions - Bad headers");
-
-                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                            request.respond(response).unwrap();
-                        } else {
-
-                            let mut content = String::new();
-                            let reader = request.as_reader();
-                            match reader.read_to_string(&mut content) {
-                                Ok(_) => {
-
-                                    match serde_json::from_str::<RequestGameOptions>(&mut content) {
-                                        Ok(deserialized_request) => {
-
-                                            let room_id = deserialized_request.room_id;
-
-                                            let player_id = deserialized_request.player_id;
-
-                                            if room_id == 0 || player_id == 0 {
-                                                println!("GameOptions - Invalid data");
-
-                                                let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                request.respond(response).unwrap();
-                                            } else {
-
-                                                let room_found = game_context.rooms.get(&room_id);
-                                                if room_found.is_none() {
-                                                    println!("GameOptions - Room {} not found", room_id);
-
-                                                    let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                    request.respond(response).unwrap();
-                                                } else {
-                                                    let players_in_room = game_context.room_players.get(&room_id).unwrap();
-
-                                                    let player_found = players_in_room.iter().find(|&p_id| p_id == &player_id);
-                                                    if player_found.is_none() {
-                                                        println!("GameOptions - Player {} not found in room {}", player_id, room_id);
-
-                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                        request.respond(response).unwrap();
-                                                    } else {
-                                                        let player_optional = game_context.players.get_mut(&player_id);
-                                                        if player_optional.is_none() {
-                                                            println!("GameOptions - Player {} not found!!!", player_id);
-
-                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                            request.respond(response).unwrap();
-                                                        } else {
-                                                            let room = room_found.unwrap();
-
-                                                            if room.leader_id == player_id {
-                                                                match &room.room_status {
-                                                                    RoomStatus::LeaderOptions => {
-                                                                        // Prompts
-                                                                        let room_prompts_optional = game_context.room_prompts.get_mut(&room_id);
-                                                                        if room_prompts_optional.is_none() {
-                                                                            println!("GameOptions - Room {} prompts not found", room_id);
-
-                                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        } else {
-                                                                            let room_prompts = room_prompts_optional.unwrap();
-
-                                                                            let room_prompt_count = room_prompts.len();
-                                                                            for _ in room_prompt_count..3 {
-                                                                                let room_available_prompts = game_context.room_available_prompts.get_mut(&room_id).unwrap();
-                                                                                let random_prompt = room_available_prompts.pop();
-                                                                                if random_prompt.is_none() {
-                                                                                    // Refill (no need to exclude any)
-
-                                                                                    // A hacky random vector generator
-                                                                                    let mut prompt_ids_in = prompt_ids.clone();
-                                                                                    let mut prompt_ids_out = vec!();
-                                                                                    while !prompt_ids_in.is_empty() {
-                                                                                        prompt_ids_out.push(prompt_ids_in.remove(game_context.rng.gen_range(0..prompt_ids_in.len())));
-                                                                                    }
-
-                                                                                    room_available_prompts.append(&mut prompt_ids_out);
-
-                                                                                    let new_random_prompt = room_available_prompts.pop().unwrap();
-                                                                                    room_prompts.push(new_random_prompt);
-                                                                                } else {
-                                                                                    room_prompts.push(random_prompt.unwrap());
-                                                                                }
-                                                                            }
-
-                                                                            let options = room_prompts.iter().map(|&prompt_position| {
-                                                                                let prompt_position_usize = usize::try_from(prompt_position).unwrap();
-                                                                                let prompt = &prompts[prompt_position_usize];
-                                                                                ResponseGameOptionsOption {
-                                                                                    option_id: prompt_position,
-                                                                                    option_text: prompt.to_string()
-                                                                                }
-                                                                            }).collect();
-
-                                                                            let response_game_options = ResponseGameOptions { options: options };
-                                                                            let serialized_response = serde_json::to_string(&response_game_options).unwrap();
-                                                                            let response_reader = BufReader::new(serialized_response.as_bytes());
-                                                                            let response = Response::new(StatusCode(200), headers, response_reader, Some(serialized_response.len()), None);
-                                                                            request.respond(response).unwrap();
-                                                                        }
-                                                                    }
-                                                                    RoomStatus::LeaderPick => {
-                                                                        // Finishers
-                                                                        let room_finishers_optional = game_context.room_finishers.get_mut(&room_id);
-                                                                        if room_finishers_optional.is_none() {
-                                                                            println!("GameOptions - Room {} finishers not found", room_id);
-
-                                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        } else {
-                                                                            let room_finishers = room_finishers_optional.unwrap();
-
-                                                                            let options = room_finishers.values().map(|&finisher_position| {
-                                                                                let finisher_position_usize = usize::try_from(finisher_position).unwrap();
-                                                                                let finisher = &finishers[finisher_position_usize];
-                                                                                ResponseGameOptionsOption {
-                                                                                    option_id: finisher_position,
-                                                                                    option_text: finisher.to_string()
-                                                                                }
-                                                                            }).collect();
-
-                                                                            let response_game_options = ResponseGameOptions { options: options };
-                                                                            let serialized_response = serde_json::to_string(&response_game_options).unwrap();
-                                                                            let response_reader = BufReader::new(serialized_response.as_bytes());
-                                                                            let response = Response::new(StatusCode(200), headers, response_reader, Some(serialized_response.len()), None);
-                                                                            request.respond(response).unwrap();
-                                                                        }
-                                                                    }
-                                                                    _ => {
-                                                                        println!("GameOptions - Player {} requested prompts on the wrong room status {}", player_id, room.room_status);
-
-                                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                        request.respond(response).unwrap();
-                                                                    }
-                                                                }
-                                                            } else {
-                                                                match &room.room_status {
-                                                                    RoomStatus::LackeyOptions => {
-                                                                        // Finishers
-                                                                        let player_finishers_optional = game_context.player_finishers.get(&player_id);
-                                                                        if player_finishers_optional.is_none() {
-                                                                            println!("GameOptions - Player {} finishers not found", player_id);
-
-                                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        } else {
-                                                                            let player_finishers = player_finishers_optional.unwrap();
-
-                                                                            let player_finisher_count = player_finishers.len();
-                                                                            for _ in player_finisher_count..8 {
-
-                                                                                let room_available_finishers = game_context.room_available_finishers.get_mut(&room_id).unwrap();
-                                                                                let random_finisher = room_available_finishers.pop();
-                                                                                if random_finisher.is_none() {
-                                                                                    let mut temp_finishers: Vec<u16> = vec![];
-                                                                                    for temp_player_id in game_context.room_players.get(&room_id).unwrap() {
-                                                                                        for temp_finisher_id in game_context.player_finishers.get(temp_player_id).unwrap() {
-                                                                                            temp_finishers.push(*temp_finisher_id);
-                                                                                        }
-                                                                                    }
-
-                                                                                    // Refill
-
-                                                                                    // A hacky random vector generator
-                                                                                    let mut finisher_ids_in = finisher_ids.clone();
-                                                                                    let mut finisher_ids_out = vec!();
-                                                                                    while !finisher_ids_in.is_empty() {
-                                                                                        finisher_ids_out.push(finisher_ids_in.remove(game_context.rng.gen_range(0..finisher_ids_in.len())));
-                                                                                    }
-
-                                                                                    room_available_finishers.append(&mut finisher_ids_out);
-
-                                                                                    let new_random_finisher = room_available_finishers.pop().unwrap();
-
-                                                                                    let player_finishers_mutable = game_context.player_finishers.get_mut(&player_id).unwrap();
-                                                                                    player_finishers_mutable.push(new_random_finisher);
-                                                                                } else {
-                                                                                    let player_finishers_mutable = game_context.player_finishers.get_mut(&player_id).unwrap();
-                                                                                    player_finishers_mutable.push(random_finisher.unwrap());
-                                                                                }
-                                                                            }
-
-                                                                            let player_finishers_again = game_context.player_finishers.get(&player_id).unwrap();
-                                                                            let options = player_finishers_again.iter().map(|&finisher_position| {
-                                                                                let finisher_position_usize = usize::try_from(finisher_position).unwrap();
-                                                                                let finisher = &finishers[finisher_position_usize];
-                                                                                ResponseGameOptionsOption {
-                                                                                    option_id: finisher_position,
-                                                                                    option_text: finisher.to_string()
-                                                                                }
-                                                                            }).collect();
-
-                                                                            let response_game_options = ResponseGameOptions { options: options };
-                                                                            let serialized_response = serde_json::to_string(&response_game_options).unwrap();
-                                                                            let response_reader = BufReader::new(serialized_response.as_bytes());
-                                                                            let response = Response::new(StatusCode(200), headers, response_reader, Some(serialized_response.len()), None);
-                                                                            request.respond(response).unwrap();
-                                                                        }
-                                                                    }
-                                                                    _ => {
-                                                                        println!("GameOptions - Player {} requested finishers on the wrong room status {}", player_id, room.room_status);
-
-                                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                        request.respond(response).unwrap();
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-
-                                        },
-                                        Err(_) => {
-                                            println!("GameOptions - Cant read JSON");
-
-                                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                            request.respond(response).unwrap();
-                                        }
-                                    };
-
-                                },
-                                Err(_) => {
-                                    println!("GameOptions - Cant read request content");
-
-                                    let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                    request.respond(response).unwrap();
-                                }
-                            }
-                        }
-                    },
-                    GameAction::GamePick => {
-                        println!("GamePick request!");
-
-                        // Could the HeaderField be a constant?
-                        let content_type_header_field = HeaderField::from_bytes(b"Content-Type").unwrap();
-                        let content_type_found = request.headers().iter().find(|&h| h.field == content_type_header_field);
-                        if content_type_found.is_none() || content_type_found.unwrap().value != "application/json; charset=UTF-8" {
-                            println!("GamePick - Bad headers");
-
-                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                            request.respond(response).unwrap();
-                        } else {
-
-                            let mut content = String::new();
-                            let reader = request.as_reader();
-                            match reader.read_to_string(&mut content) {
-                                Ok(_) => {
-
-                                    match serde_json::from_str::<RequestGamePick>(&mut content) {
-                                        Ok(deserialized_request) => {
-
-                                            let room_id = deserialized_request.room_id;
-
-                                            let player_id = deserialized_request.player_id;
-
-                                            let option_id = deserialized_request.option_id;
-
-                                            // No need to validate the option_id as it can be zero!
-                                            if room_id == 0 || player_id == 0 {
-                                                println!("GamePick - Invalid data");
-
-                                                let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                request.respond(response).unwrap();
-                                            } else {
-
-                                                let room_found = game_context.rooms.get_mut(&room_id);
-                                                if room_found.is_none() {
-                                                    println!("GamePick - Room {} not found", room_id);
-
-                                                    let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                    request.respond(response).unwrap();
-                                                } else {
-                                                    let players_in_room = game_context.room_players.get(&room_id).unwrap();
-
-                                                    let player_found = players_in_room.iter().find(|&p_id| p_id == &player_id);
-                                                    if player_found.is_none() {
-                                                        println!("GamePick - Player {} not found in room {}", player_id, room_id);
-
-                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                        request.respond(response).unwrap();
-                                                    } else {
-                                                        let player_optional = game_context.players.get_mut(&player_id);
-                                                        if player_optional.is_none() {
-                                                            println!("GamePick - Player {} not found!!!", player_id);
-
-                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                            request.respond(response).unwrap();
-                                                        } else {
-                                                            let room = room_found.unwrap();
-
-                                                            if room.leader_id == player_id {
-                                                                match &room.room_status {
-                                                                    RoomStatus::LeaderOptions => {
-                                                                        // Prompts
-                                                                        let room_prompts_optional = game_context.room_prompts.get(&room_id);
-                                                                        if room_prompts_optional.is_none() {
-                                                                            println!("GamePick - Player {} leader prompts not found", player_id);
-
-                                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        } else {
-                                                                            let room_prompts = room_prompts_optional.unwrap();
-
-                                                                            let player_prompt_found = room_prompts.iter().find(|&pr_id| pr_id == &option_id);
-                                                                            if player_prompt_found.is_none() {
-                                                                                println!("GamePick - Player {} leader prompt {} not found", player_id, option_id);
-
-                                                                                let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                                request.respond(response).unwrap();
-                                                                            } else {
-
-                                                                                let room_finishers_optional = game_context.room_finishers.get_mut(&room_id);
-                                                                                if room_finishers_optional.is_none() {
-                                                                                    println!("GamePick - Room {} finishers not found", room_id);
-
-                                                                                    let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                                    request.respond(response).unwrap();
-                                                                                } else {
-                                                                                    // Need to clean it so that the lackeys can place new cards on it
-                                                                                    room_finishers_optional.unwrap().clear();
-
-                                                                                    room.room_status = RoomStatus::LackeyOptions;
-                                                                                    room.selected_prompt_id = Some(option_id);
-
-                                                                                    let response = Response::new(StatusCode(204), headers, io::empty(), None, None);
-                                                                                    request.respond(response).unwrap();
-                                                                                }
-                                                                            }
-                                                                        }
-                                                                    },
-                                                                    RoomStatus::LeaderPick => {
-                                                                        let room_finishers_optional = game_context.room_finishers.get_mut(&room_id);
-                                                                        if room_finishers_optional.is_none() {
-                                                                            println!("GamePick - Room {} finishers not found", room_id);
-
-                                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        } else {
-                                                                            let room_finishers = room_finishers_optional.unwrap();
-
-                                                                            let player_finisher_found = room_finishers.iter().find(|&(_, &val)| val == option_id);
-                                                                            if player_finisher_found.is_none() {
-                                                                                println!("GamePick - Player {} finisher prompt {} not found", player_id, option_id);
-
-                                                                                let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                                request.respond(response).unwrap();
-                                                                            } else {
-                                                                                let player_finisher = player_finisher_found.unwrap();
-
-                                                                                let winner_player_id = player_finisher.0;
-                                                                                let winner_player_optional = game_context.players.get_mut(&winner_player_id);
-                                                                                if winner_player_optional.is_none() {
-                                                                                    println!("GamePick - Player {} not found", winner_player_id);
-
-                                                                                    let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                                    request.respond(response).unwrap();
-                                                                                } else {
-                                                                                    let winner_player = winner_player_optional.unwrap();
-
-                                                                                    let room_players_not_ready_found = game_context.room_players_not_ready.get_mut(&room_id);
-                                                                                    if room_players_not_ready_found.is_none() {
-                                                                                        println!("GamePick - Room {} of Players not Ready not found", room_id);
-
-                                                                                        let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                                        request.respond(response).unwrap();
-                                                                                    } else {
-                                                                                        // No player is ready now (including leader)
-                                                                                        let mut clone = players_in_room.clone();
-                                                                                        room_players_not_ready_found.unwrap().append(&mut clone);
-
-
-                                                                                        winner_player.score += 1;
-
-                                                                                        room.room_status = RoomStatus::RoundWinner;
-                                                                                        room.winner_player_id = Some(*winner_player_id);
-                                                                                        room.winner_finisher_id = Some(option_id);
-
-                                                                                        let response = Response::new(StatusCode(204), headers, io::empty(), None, None);
-                                                                                        request.respond(response).unwrap();
-                                                                                    }
-                                                                                }
-                                                                            }
-                                                                        }
-                                                                    },
-                                                                    _ => {
-                                                                        println!("GamePick - Player {} leader picked an option on the wrong room status {}", player_id, room.room_status);
-
-                                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                        request.respond(response).unwrap();
-                                                                    }
-                                                                }
-                                                            } else {
-                                                                match &room.room_status {
-                                                                    RoomStatus::LackeyOptions => {
-                                                                        // Finishers
-                                                                        let player_finishers_optional = game_context.player_finishers.get_mut(&player_id);
-                                                                        if player_finishers_optional.is_none() {
-                                                                            println!("GameOptions - Player {} lackey finishers not found", player_id);
-
-                                                                            let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                            request.respond(response).unwrap();
-                                                                        } else {
-                                                                            let player_finishers = player_finishers_optional.unwrap();
-
-                                                                            let player_finisher_found = player_finishers.iter().find(|&f_id| f_id == &option_id);
-                                                                            if player_finisher_found.is_none() {
-                                                                                println!("GamePick - Player {} lackey finisher {} not found", player_id, option_id);
-
-                                                                                let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                                request.respond(response).unwrap();
-                                                                            } else {
-                                                                                let room_finishers_optional = game_context.room_finishers.get_mut(&room_id);
-                                                                                if room_finishers_optional.is_none() {
-                                                                                    println!("GamePick - Room {} finishers not found", room_id);
-
-                                                                                    let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                                                                                    request.respond(response).unwrap();
-                                                                                } else {
-                                                                                    let room_finishers = room_finishers_optional.unwrap();
-
-                                                                                    let room_finisher_player_found = room_finishers.get(&player_id);
-                                                                                    if room_finisher_player_found.is_none() {
-                                                                                        //TODO: This operation here should be atomic to prevent weird game states...
-                                                                                        player_finishers.retain(|&f| f != option_id);
-
-                                                                                        room_finishers.insert(player_id, option_id);
-
-                                                                                        let mut all_players_submitted_finishers = true;
-                                                                                        for room_player_id in players_in_room {
-                                                                                            if room_player_id != &room.leader_id {
-                                                                                                let player_submitted_finisher = room_finishers.get(room_player_id);
-                                                                                                if player_submitted_finisher.is_none() {
-                                                                                                    all_players_submitted_finishers = false;
-                                                                                                    break;
-                                                                                                }
-                                                                                            }
-                                                                                        }
-
-                                                                                        //TODO: What about dead clients?
-                                                                                        //      Do we wait for the job to disconnect them?
-                                                                                        //      Or should they just be ignored during the check?
-                                                                                        //TODO: Can we have the owner or leader force things?
-                                                                                        if all_players_submitted_finishers {
-                                                                                            room.room_status = RoomStatus::LeaderPick;
-                                                                                        }
-
-                                                                                        let response = Response::new(StatusCode(204), headers, io::empty(), None, None);
-                                                                                        request.respond(response).unwrap();
-                                                                                    } else {
-                                                                                        println!("GamePick - Player {} lackey finisher already submitted", player_id);
-
-                                                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                                        request.respond(response).unwrap();
-                                                                                    }
-                                                                                }
-                                                                            }
-                                                                        }
-                                                                    },
-                                                                    _ => {
-                                                                        println!("GameOptions - Player {} lackey picked a finisher on the wrong room status {}", player_id, room.room_status);
-
-                                                                        let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                                                        request.respond(response).unwrap();
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-
-                                        },
-                                        Err(_) => {
-                                            println!("GameOptions - Cant read JSON");
-
-                                            let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                            request.respond(response).unwrap();
-                                        }
-                                    };
-
-                                },
-                                Err(_) => {
-                                    println!("GameOptions - Cant read request content");
-
-                                    let response = Response::new(StatusCode(400), headers, io::empty(), None, None);
-                                    request.respond(response).unwrap();
-                                }
+        let changed_room_ids = RoomSockets::poll(&socket_room_sockets, &socket_game_engine);
+        for changed_room_id in changed_room_ids {
+            publish_room_state(&socket_game_engine, &socket_room_events, &socket_room_sockets, changed_room_id);
+        }
+    });
+
+    // Each worker takes the next request as soon as it is done with the previous one,
+    // so a client that is slow to send its body only holds back its own worker
+    let server = Arc::new(server);
+    let workers: Vec<thread::JoinHandle<()>> = (0..worker_count).map(|_| {
+        let server = Arc::clone(&server);
+        let game_engine = Arc::clone(&game_engine);
+        let room_events = Arc::clone(&room_events);
+        let room_sockets = Arc::clone(&room_sockets);
+
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                handle_request(request, &game_engine, &room_events, &room_sockets);
+            }
+        })
+    }).collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    println!("Shutting down.");
+}
+
+fn handle_request(mut request: Request, game_engine: &GameEngine, room_events: &Mutex<RoomEvents>, room_sockets: &Mutex<RoomSockets>) {
+    // This can be quite noisy with all the RoomCheck requests:
+    // println!("received request! method: {:?}, url: {:?}", request.method(), request.url());
+
+    match get_game_action(request.method(), request.url()) {
+        Some(game_action) => {
+            let headers = cors_headers();
+
+            match game_action {
+                GameAction::CorsOption => respond_empty(request, headers, 200),
+                GameAction::RoomCreate => {
+                    println!("RoomCreate request!");
+
+                    match read_json::<RequestRoomCreate>(&mut request) {
+                        Ok(deserialized_request) => match game_engine.create_room(&deserialized_request.owner_name, deserialized_request.settings) {
+                            Ok(response_room_create) => respond_json(request, headers, 201, &response_room_create),
+                            Err(error) => respond_error(request, headers, "RoomCreate", error)
+                        },
+                        Err(error) => respond_error(request, headers, "RoomCreate", error)
+                    }
+                },
+                GameAction::RoomJoin => {
+                    println!("RoomJoin request!");
+
+                    match read_json::<RequestRoomJoin>(&mut request) {
+                        Ok(deserialized_request) => match game_engine.join_room(&deserialized_request.room_code, &deserialized_request.player_name) {
+                            Ok(response_room_join) => {
+                                respond_json(request, headers, 200, &response_room_join);
+                                publish_room_state(game_engine, room_events, room_sockets, response_room_join.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "RoomJoin", error)
+                        },
+                        Err(error) => respond_error(request, headers, "RoomJoin", error)
+                    }
+                },
+                GameAction::RoomRejoin => {
+                    println!("RoomRejoin request!");
+
+                    match read_json::<RequestRoomRejoin>(&mut request) {
+                        Ok(deserialized_request) => match game_engine.rejoin_room(&deserialized_request.room_code, &deserialized_request.reconnect_token) {
+                            Ok(response_room_join) => {
+                                respond_json(request, headers, 200, &response_room_join);
+                                // Whoever still holds the old session is out
+                                close_player(room_events, room_sockets, response_room_join.room_id, response_room_join.player_id);
+                                publish_room_state(game_engine, room_events, room_sockets, response_room_join.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "RoomRejoin", error)
+                        },
+                        Err(error) => respond_error(request, headers, "RoomRejoin", error)
+                    }
+                },
+                GameAction::RoomCheck => {
+                    // println!("RoomCheck request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestRoomCheck>(&mut request) {
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.check(deserialized_request.room_id, deserialized_request.player_id)) {
+                            Ok(response_room_check) => respond_json(request, headers, 200, &response_room_check),
+                            Err(error) => respond_error(request, headers, "RoomCheck", error)
+                        },
+                        Err(error) => respond_error(request, headers, "RoomCheck", error)
+                    }
+                },
+                GameAction::RoomLeave => {
+                    println!("RoomLeave request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestRoomLeave>(&mut request) {
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.leave_room(deserialized_request.room_id, deserialized_request.player_id)) {
+                            Ok(response_room_leave) => {
+                                respond_json(request, headers, 200, &response_room_leave);
+                                close_player(room_events, room_sockets, deserialized_request.room_id, deserialized_request.player_id);
+                                publish_room_state(game_engine, room_events, room_sockets, deserialized_request.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "RoomLeave", error)
+                        },
+                        Err(error) => respond_error(request, headers, "RoomLeave", error)
+                    }
+                },
+                GameAction::RoomSettings => {
+                    println!("RoomSettings request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestRoomSettings>(&mut request) {
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.update_settings(deserialized_request.room_id, deserialized_request.player_id, deserialized_request.settings)) {
+                            Ok(response_room_settings) => {
+                                respond_json(request, headers, 200, &response_room_settings);
+                                publish_room_state(game_engine, room_events, room_sockets, deserialized_request.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "RoomSettings", error)
+                        },
+                        Err(error) => respond_error(request, headers, "RoomSettings", error)
+                    }
+                },
+                GameAction::RoomKick => {
+                    println!("RoomKick request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestRoomKick>(&mut request) {
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.kick_player(deserialized_request.room_id, deserialized_request.player_id, deserialized_request.target_player_id, deserialized_request.ban)) {
+                            Ok(response_room_kick) => {
+                                respond_json(request, headers, 200, &response_room_kick);
+                                close_player(room_events, room_sockets, deserialized_request.room_id, deserialized_request.target_player_id);
+                                publish_room_state(game_engine, room_events, room_sockets, deserialized_request.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "RoomKick", error)
+                        },
+                        Err(error) => respond_error(request, headers, "RoomKick", error)
+                    }
+                },
+                GameAction::RoomTransfer => {
+                    println!("RoomTransfer request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestRoomTransfer>(&mut request) {
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.transfer_ownership(deserialized_request.room_id, deserialized_request.player_id, deserialized_request.target_player_id)) {
+                            Ok(response_room_transfer) => {
+                                respond_json(request, headers, 200, &response_room_transfer);
+                                publish_room_state(game_engine, room_events, room_sockets, deserialized_request.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "RoomTransfer", error)
+                        },
+                        Err(error) => respond_error(request, headers, "RoomTransfer", error)
+                    }
+                },
+                GameAction::RoomLock => {
+                    println!("RoomLock request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestRoomLock>(&mut request) {
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.lock_room(deserialized_request.room_id, deserialized_request.player_id, deserialized_request.is_locked)) {
+                            Ok(response_room_lock) => {
+                                respond_json(request, headers, 200, &response_room_lock);
+                                publish_room_state(game_engine, room_events, room_sockets, deserialized_request.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "RoomLock", error)
+                        },
+                        Err(error) => respond_error(request, headers, "RoomLock", error)
+                    }
+                },
+                GameAction::RoomEvents => {
+                    println!("RoomEvents request!");
+
+                    let room_id = path_room_id(request.url()).unwrap_or(0);
+                    let player_id = query_param(request.url(), "player_id").and_then(|p| p.parse::<u32>().ok()).unwrap_or(0);
+                    // EventSource cannot send headers, so the token comes in the query
+                    let player_token = query_param(request.url(), "player_token");
+
+                    match authenticate(game_engine, room_id, player_id, player_token).and_then(|_| game_engine.check(room_id, player_id)) {
+                        Ok(response_room_check) => {
+                            match open_event_stream(request) {
+                                Ok(writer) => room_events.lock().unwrap().subscribe(room_id, player_id, writer, &response_room_check),
+                                Err(error) => println!("RoomEvents - Cant open event stream: {}", error)
                             }
-                        }
+                        },
+                        Err(error) => respond_error(request, headers, "RoomEvents", error)
                     }
-                    // _ => {
-                    //     println!("Unknown Request");
+                },
+                GameAction::RoomHistory => {
+                    println!("RoomHistory request!");
 
-                    //     let response = Response::new(StatusCode(500), headers, io::empty(), None, None);
-                    //     request.respond(response).unwrap();
-                    // }
-                }
+                    let room_id = path_room_id(request.url()).unwrap_or(0);
+                    let player_id = query_param(request.url(), "player_id").and_then(|p| p.parse::<u32>().ok()).unwrap_or(0);
+                    let player_token = authorization_token(&request);
 
-            },
-            None => request.respond(Response::empty(400)).unwrap()
-        };
-    }
+                    match authenticate(game_engine, room_id, player_id, player_token.as_deref()).and_then(|_| game_engine.history(room_id, player_id)) {
+                        Ok(response_room_history) => respond_json(request, headers, 200, &response_room_history),
+                        Err(error) => respond_error(request, headers, "RoomHistory", error)
+                    }
+                },
+                GameAction::GameStart => {
+                    println!("GameStart request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestGameStart>(&mut request) {
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.start(deserialized_request.room_id, deserialized_request.player_id)) {
+                            Ok(response_game_start) => {
+                                respond_json(request, headers, 200, &response_game_start);
+                                publish_room_state(game_engine, room_events, room_sockets, deserialized_request.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "GameStart", error)
+                        },
+                        Err(error) => respond_error(request, headers, "GameStart", error)
+                    }
+                },
+                GameAction::GameForceAdvance => {
+                    println!("GameForceAdvance request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestGameForceAdvance>(&mut request) {
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.force_advance(deserialized_request.room_id, deserialized_request.player_id)) {
+                            Ok(response_game_force_advance) => {
+                                respond_json(request, headers, 200, &response_game_force_advance);
+                                publish_room_state(game_engine, room_events, room_sockets, deserialized_request.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "GameForceAdvance", error)
+                        },
+                        Err(error) => respond_error(request, headers, "GameForceAdvance", error)
+                    }
+                },
+                GameAction::GameOptions => {
+                    println!("GameOptions request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestGameOptions>(&mut request) {
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.options(deserialized_request.room_id, deserialized_request.player_id)) {
+                            Ok(response_game_options) => respond_json(request, headers, 200, &response_game_options),
+                            Err(error) => respond_error(request, headers, "GameOptions", error)
+                        },
+                        Err(error) => respond_error(request, headers, "GameOptions", error)
+                    }
+                },
+                GameAction::GamePick => {
+                    println!("GamePick request!");
+
+                    let player_token = authorization_token(&request);
+                    match read_json::<RequestGamePick>(&mut request) {
+                        // No need to validate the option_id as it can be zero!
+                        Ok(deserialized_request) => match authenticate(game_engine, deserialized_request.room_id, deserialized_request.player_id, player_token.as_deref())
+                            .and_then(|_| game_engine.pick(deserialized_request.room_id, deserialized_request.player_id, deserialized_request.option_id)) {
+                            Ok(response_game_pick) => {
+                                respond_json(request, headers, 200, &response_game_pick);
+                                publish_room_state(game_engine, room_events, room_sockets, deserialized_request.room_id);
+                            },
+                            Err(error) => respond_error(request, headers, "GamePick", error)
+                        },
+                        Err(error) => respond_error(request, headers, "GamePick", error)
+                    }
+                }
+            }
 
-    println!("Shutting down.");
+        },
+        None => respond_error(request, cors_headers(), "Unknown", GameError::UnknownAction)
+    };
 }
 
 fn get_game_action(method: &Method, url: &str) -> Option<GameAction> {
     match method {
-        // Method::Get => match url {
-        //     _ => return None
-        // },
+        Method::Get => match url_path(url).split('/').collect::<Vec<&str>>()[..] {
+            ["", "rooms", _, "events"] => Some(GameAction::RoomEvents),
+            ["", "rooms", _, "history"] => Some(GameAction::RoomHistory),
+            _ => None
+        },
         Method::Post => match url {
-            "/room-create" => return Some(GameAction::RoomCreate),
-            "/room-join" => return Some(GameAction::RoomJoin),
+            "/room-create" => Some(GameAction::RoomCreate),
+            "/room-join" => Some(GameAction::RoomJoin),
+            "/room-rejoin" => Some(GameAction::RoomRejoin),
             //TODO: I know that the Room Check should be a GET, but I don't want to parse the Request's  URL parameters manually.
-            "/room-check" => return Some(GameAction::RoomCheck),
-            "/game-start" => return Some(GameAction::GameStart),
+            "/room-check" => Some(GameAction::RoomCheck),
+            "/room-leave" => Some(GameAction::RoomLeave),
+            "/room-settings" => Some(GameAction::RoomSettings),
+            "/room-kick" => Some(GameAction::RoomKick),
+            "/room-transfer" => Some(GameAction::RoomTransfer),
+            "/room-lock" => Some(GameAction::RoomLock),
+            "/game-start" => Some(GameAction::GameStart),
+            "/game-force-advance" => Some(GameAction::GameForceAdvance),
             //TODO: I know that the Game Status should be a GET, but I don't want to parse the Request's  URL parameters manually.
-            "/game-options" => return Some(GameAction::GameOptions),
-            "/game-pick" => return Some(GameAction::GamePick),
-            _ => return None
+            "/game-options" => Some(GameAction::GameOptions),
+            "/game-pick" => Some(GameAction::GamePick),
+            _ => None
         },
-        Method::Options => return Some(GameAction::CorsOption),
-        _ => return None
-    };
-}
-
-impl fmt::Display for RoomStatus {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            RoomStatus::Waiting => write!(f, "WAITING"),
-            RoomStatus::LeaderOptions => write!(f, "LEADER_OPTIONS"),
-            RoomStatus::LackeyOptions => write!(f, "LACKEY_OPTIONS"),
-            RoomStatus::LeaderPick => write!(f, "LEADER_PICK"),
-            RoomStatus::RoundWinner => write!(f, "ROUND_WINNER"),
-            RoomStatus::GameWinner => write!(f, "GAME_WINNER")
-        }
+        Method::Options => Some(GameAction::CorsOption),
+        _ => None
     }
 }
 
-
-struct GameContext<'a> {
-    rng: &'a mut ThreadRng,
-    rooms: &'a mut HashMap<u32, Room>,
-    players: &'a mut HashMap<u32, Player>,
-    room_players: &'a mut HashMap<u32, Vec<u32>>,
-    room_prompts: &'a mut HashMap<u32, Vec<u16>>,
-    room_finishers: &'a mut HashMap<u32, HashMap<u32, u16>>, // Inner map: PlayerId, FinisherId
-    player_finishers: &'a mut HashMap<u32, Vec<u16>>,
-    room_available_prompts: &'a mut HashMap<u32, Vec<u16>>,
-    room_available_finishers: &'a mut HashMap<u32, Vec<u16>>,
-    room_players_not_ready: &'a mut HashMap<u32, Vec<u32>>
+// Hack for development
+fn cors_headers() -> Vec<Header> {
+    let access_control_allow_headers = Header::from_bytes(b"Access-Control-Allow-Headers", b"Authorization, *").unwrap();
+    let access_control_allow_origin_header = Header::from_bytes(b"Access-Control-Allow-Origin", b"*").unwrap();
+    let access_control_allow_methods = Header::from_bytes(b"Access-Control-Allow-Methods", b"GET, POST").unwrap();
+    let access_control_allow_max_age = Header::from_bytes(b"Access-Control-Max-Age", b"3600").unwrap(); // 3600 = 1 hour
+    Vec::from([access_control_allow_headers, access_control_allow_origin_header, access_control_allow_methods, access_control_allow_max_age])
 }
 
-enum GameAction {
-    CorsOption,
-    RoomCreate,
-    RoomJoin,
-    RoomCheck,
-    GameStart,
-    GameOptions,
-    GamePick
+fn url_path(url: &str) -> &str {
+    url.split('?').next().unwrap_or_default()
 }
 
-struct Room {
-    id: u32,
-    code: String,
-    room_status: RoomStatus,
-    owner_id: u32,
-    leader_id: u32,
-    round_counter: u8,
-    round_total: u8,
-    leader_player_position: u8,
-    selected_prompt_id: Option<u16>,
-    winner_player_id: Option<u32>,
-    winner_finisher_id: Option<u16>
+// Paths look like "/rooms/{room_id}/..."
+fn path_room_id(url: &str) -> Option<u32> {
+    url_path(url).split('/').nth(2).and_then(|room_id| room_id.parse::<u32>().ok())
 }
 
-enum RoomStatus {
-    Waiting,
-    LeaderOptions,
-    LackeyOptions,
-    LeaderPick,
-    RoundWinner,
-    GameWinner
+fn query_param<'a>(url: &'a str, name: &str) -> Option<&'a str> {
+    let (_, query) = url.split_once('?')?;
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|&(key, _)| key == name)
+        .map(|(_, value)| value)
 }
 
-struct Player {
-    id: u32,
-    name: String,
-    score: u8,
-    last_check: Instant
+// Tokens are sent as "Authorization: Bearer {player_token}"
+fn authorization_token(request: &Request) -> Option<String> {
+    let authorization_header_field = HeaderField::from_bytes(b"Authorization").unwrap();
+    let authorization_found = request.headers().iter().find(|&h| h.field == authorization_header_field)?;
+    authorization_found.value.as_str().strip_prefix("Bearer ").map(|player_token| player_token.trim().to_string())
 }
 
-
-#[derive(Deserialize, Debug)]
-struct RequestRoomCreate {
-    owner_name: String
+fn authenticate(game_engine: &GameEngine, room_id: u32, player_id: u32, player_token: Option<&str>) -> Result<(), GameError> {
+    match player_token {
+        Some(player_token) => game_engine.authenticate(room_id, player_id, player_token),
+        None => Err(GameError::NotAuthenticated)
+    }
 }
 
-#[derive(Serialize, Debug)]
-struct ResponseRoomCreate {
-    room_id: u32,
-    room_code: String,
-    player_id: u32
-}
+fn read_json<T: DeserializeOwned>(request: &mut Request) -> Result<T, GameError> {
+    let content_type_header_field = HeaderField::from_bytes(b"Content-Type").unwrap();
+    let content_type_found = request.headers().iter().find(|&h| h.field == content_type_header_field);
+    match content_type_found {
+        Some(content_type) if content_type.value == "application/json" || content_type.value == "application/json; charset=UTF-8" => {},
+        _ => return Err(GameError::BadHeaders)
+    }
 
+    let mut content = String::new();
+    if request.as_reader().read_to_string(&mut content).is_err() {
+        return Err(GameError::UnreadableContent);
+    }
 
-#[derive(Deserialize, Debug)]
-struct RequestRoomJoin {
-    player_name: String,
-    room_code: String
+    serde_json::from_str::<T>(&content).map_err(|_| GameError::InvalidJson)
 }
 
-#[derive(Serialize, Debug)]
-struct ResponseRoomJoin {
-    room_id: u32,
-    player_id: u32
+fn respond_empty(request: Request, headers: Vec<Header>, status: u16) {
+    let response = Response::new(StatusCode(status), headers, io::empty(), None, None);
+    if let Err(error) = request.respond(response) {
+        println!("Could not send response: {}", error);
+    }
 }
 
-
-#[derive(Deserialize, Debug)]
-struct RequestRoomCheck {
-    room_id: u32,
-    player_id: u32
+fn respond_json<T: Serialize>(request: Request, headers: Vec<Header>, status: u16, body: &T) {
+    let serialized_response = serde_json::to_string(body).unwrap();
+    let response_reader = BufReader::new(serialized_response.as_bytes());
+    let response = Response::new(StatusCode(status), headers, response_reader, Some(serialized_response.len()), None);
+    if let Err(error) = request.respond(response) {
+        println!("Could not send response: {}", error);
+    }
 }
 
-#[derive(Serialize, Debug)]
-struct ResponseRoomCheck {
-    players: Vec<ResponseRoomCheckPlayer>,
-    room_status: String,
-    owner_id: u32,
-    leader_id: u32,
-    round_counter: u8,
-    round_total: u8,
-    prompt_text: Option<String>,
-    finishers: Option<Vec<ResponseRoomCheckFinisher>>
+fn open_event_stream(request: Request) -> io::Result<Box<dyn Write + Send>> {
+    let mut writer = request.into_writer();
+    // RoomEvents writes the events as chunks
+    write!(writer, "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\nTransfer-Encoding: chunked\r\nAccess-Control-Allow-Origin: *\r\n\r\n")?;
+    writer.flush()?;
+    Ok(writer)
 }
 
-#[derive(Serialize, Debug)]
-struct ResponseRoomCheckPlayer {
-    player_id: u32,
-    player_name: String,
-    score: u8,
-    is_finisher_ready: Option<bool>,
-    is_next_round_ready: Option<bool>,
-    last_check: u16
-}
+fn publish_room_state(game_engine: &GameEngine, room_events: &Mutex<RoomEvents>, room_sockets: &Mutex<RoomSockets>, room_id: u32) {
+    if !room_events.lock().unwrap().has_subscribers(room_id) && !room_sockets.lock().unwrap().has_subscribers(room_id) {
+        return;
+    }
 
-#[derive(Serialize, Debug)]
-struct ResponseRoomCheckFinisher {
-    player_name: String,
-    finisher_text: String,
-    is_winner: bool
+    match game_engine.room_state(room_id) {
+        Ok(room_state) => {
+            room_events.lock().unwrap().publish(room_id, &room_state);
+            room_sockets.lock().unwrap().publish(room_id, &room_state);
+        },
+        Err(error) => println!("RoomEvents - Cant publish room {}: {}", room_id, error)
+    }
 }
 
-
-#[derive(Deserialize, Debug)]
-struct RequestGameStart {
-    room_id: u32,
-    player_id: u32
+// Before publishing the room state, so that a player that left, was kicked or rejoined elsewhere does not get it
+fn close_player(room_events: &Mutex<RoomEvents>, room_sockets: &Mutex<RoomSockets>, room_id: u32, player_id: u32) {
+    room_events.lock().unwrap().close_player(room_id, player_id);
+    room_sockets.lock().unwrap().close_player(room_id, player_id);
 }
 
-
-#[derive(Deserialize, Debug)]
-struct RequestGameOptions {
-    room_id: u32,
-    player_id: u32
+// Handshakes happen in their own threads, so a slow client cannot hold back the rest
+fn accept_websockets(websocket_listener: TcpListener, websocket_sender: Sender<WebSocket<TcpStream>>) {
+    for stream in websocket_listener.incoming().flatten() {
+        let websocket_sender = websocket_sender.clone();
+        thread::spawn(move || {
+            if let Err(error) = stream.set_read_timeout(Some(SOCKET_HANDSHAKE_TIMEOUT))
+                .and_then(|_| stream.set_write_timeout(Some(SOCKET_HANDSHAKE_TIMEOUT))) {
+                println!("WebSocket - Cant set the handshake timeout: {}", error);
+                return;
+            }
+
+            match tungstenite::accept(stream) {
+                Ok(websocket) => {
+                    let _ = websocket_sender.send(websocket);
+                },
+                Err(error) => println!("WebSocket - Handshake failed: {}", error)
+            }
+        });
+    }
 }
 
-#[derive(Serialize, Debug)]
-struct ResponseGameOptions {
-    options: Vec<ResponseGameOptionsOption>
-}
+fn respond_error(request: Request, headers: Vec<Header>, action_name: &str, error: GameError) {
+    println!("{} - {}", action_name, error);
 
-#[derive(Serialize, Debug)]
-struct ResponseGameOptionsOption {
-    option_id: u16,
-    option_text: String
+    respond_json(request, headers, error.http_status(), &error.to_response());
 }
 
-
-#[derive(Deserialize, Debug)]
-struct RequestGamePick {
-    room_id: u32,
-    player_id: u32,
-    option_id: u16
+enum GameAction {
+    CorsOption,
+    RoomCreate,
+    RoomJoin,
+    RoomRejoin,
+    RoomCheck,
+    RoomLeave,
+    RoomSettings,
+    RoomKick,
+    RoomTransfer,
+    RoomLock,
+    RoomEvents,
+    RoomHistory,
+    GameStart,
+    GameForceAdvance,
+    GameOptions,
+    GamePick
 }
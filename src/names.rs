@@ -0,0 +1,116 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::error::GameError;
+
+
+// Counted in what players see as characters, so "José" and "👍🏽" are 4 and 1 long
+pub const MAX_PLAYER_NAME_LENGTH: u8 = 16;
+// Enough for any real language, not enough to pile accents on top of each other
+const MAX_MARKS_PER_CHARACTER: usize = 2;
+
+/// Cleans up a player name and checks it is fit to be shown to everyone else in the room.
+///
+/// Control and invisible characters (zero-width spaces and joiners, direction overrides, blank fillers...)
+/// are removed, the name is normalized to NFC and any run of whitespace becomes a single space.
+pub fn sanitize_player_name(player_name: &str) -> Result<String, GameError> {
+    // Removing characters can leave accents next to new letters, so the normalization goes last
+    let visible_player_name: String = player_name.chars().filter(|&c| !is_invisible(c)).nfc().collect();
+    let sanitized_player_name = visible_player_name.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    if sanitized_player_name.is_empty() {
+        return Err(GameError::PlayerNameEmpty);
+    }
+
+    let characters: Vec<&str> = sanitized_player_name.graphemes(true).collect();
+    if characters.len() > usize::from(MAX_PLAYER_NAME_LENGTH) {
+        return Err(GameError::PlayerNameTooLong(MAX_PLAYER_NAME_LENGTH));
+    }
+
+    if characters.iter().any(|character| character.chars().filter(|&c| is_combining_mark(c)).count() > MAX_MARKS_PER_CHARACTER) {
+        return Err(GameError::PlayerNameInvalidCharacters);
+    }
+
+    Ok(sanitized_player_name)
+}
+
+/// Tells apart a player from another one with the same name, as in "Juan (2)", keeping within the length limit
+pub fn suffixed_player_name(player_name: &str, number: u32) -> String {
+    let suffix = format!(" ({})", number);
+    let kept_length = usize::from(MAX_PLAYER_NAME_LENGTH).saturating_sub(suffix.len());
+    let kept_player_name: String = player_name.graphemes(true).take(kept_length).collect();
+
+    format!("{}{}", kept_player_name.trim_end(), suffix)
+}
+
+/// Lowercase and without accents, so names that only differ in those compare as equal
+pub fn name_key(player_name: &str) -> String {
+    player_name.nfd().filter(|&c| !is_combining_mark(c)).collect::<String>().to_lowercase()
+}
+
+// Tabs and line breaks are control characters too, but those are left for the whitespace collapsing
+fn is_invisible(c: char) -> bool {
+    (c.is_control() && !c.is_whitespace()) || matches!(c,
+        '\u{00AD}' | // Soft hyphen
+        '\u{061C}' | // Arabic letter mark
+        '\u{115F}' | '\u{1160}' | // Hangul fillers
+        '\u{180E}' | // Mongolian vowel separator
+        '\u{200B}'..='\u{200F}' | // Zero-width space, non-joiner and joiner, direction marks
+        '\u{202A}'..='\u{202E}' | // Direction embeddings and overrides
+        '\u{2060}'..='\u{2064}' | // Word joiner and invisible operators
+        '\u{2066}'..='\u{206F}' | // Direction isolates and deprecated formatting
+        '\u{2800}' | // Braille blank
+        '\u{3164}' | '\u{FFA0}' | // More Hangul fillers
+        '\u{FEFF}' | // Zero-width no-break space
+        '\u{FFF9}'..='\u{FFFB}' // Interlinear annotations
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use unicode_normalization::is_nfc;
+
+    #[test]
+    fn counts_characters_instead_of_bytes() {
+        assert_eq!(sanitize_player_name("Ñoño Pérez Gálvez"), Err(GameError::PlayerNameTooLong(MAX_PLAYER_NAME_LENGTH)));
+        assert_eq!(sanitize_player_name("Ñoño Pérez Gálv"), Ok("Ñoño Pérez Gálv".to_string()));
+        assert_eq!(sanitize_player_name(&"👍🏽".repeat(16)), Ok("👍🏽".repeat(16)));
+    }
+
+    #[test]
+    fn removes_invisible_characters_and_extra_whitespace() {
+        assert_eq!(sanitize_player_name("  Ana\u{200B}  María\t\u{202E}X\u{7} "), Ok("Ana María X".to_string()));
+        for blank_player_name in ["\u{3164}", "\u{115F}\u{1160}", "\u{2800}", "\u{FFA0}", "\u{200D}\u{200B} "] {
+            assert_eq!(sanitize_player_name(blank_player_name), Err(GameError::PlayerNameEmpty));
+        }
+    }
+
+    #[test]
+    fn normalizes_after_removing_characters() {
+        let sanitized_player_name = sanitize_player_name("Jose\u{200B}\u{0301}").unwrap();
+        assert_eq!(sanitized_player_name, "José");
+        assert!(is_nfc(&sanitized_player_name));
+    }
+
+    #[test]
+    fn rejects_piled_up_accents() {
+        assert_eq!(sanitize_player_name("Ze\u{301}\u{302}\u{303}\u{304}"), Err(GameError::PlayerNameInvalidCharacters));
+        assert_eq!(sanitize_player_name("Nguyễn"), Ok("Nguyễn".to_string()));
+    }
+
+    #[test]
+    fn keys_ignore_case_and_accents() {
+        assert_eq!(name_key("JUÁN"), name_key("juan"));
+        assert_ne!(name_key("Juana"), name_key("Juan"));
+    }
+
+    #[test]
+    fn suffixes_keep_within_the_length_limit() {
+        assert_eq!(suffixed_player_name("Juan", 2), "Juan (2)");
+        assert_eq!(suffixed_player_name("Juan Carlos de León", 10), "Juan Carlos (10)");
+        assert_eq!(suffixed_player_name("Juan Carlos", 100), "Juan Carlo (100)");
+    }
+}
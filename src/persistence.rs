@@ -0,0 +1,61 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+
+/// Somewhere to keep the rooms, so that they survive a restart of the server.
+///
+/// The engine takes care of turning the rooms into a snapshot and back, a store only needs
+/// to keep the latest snapshot around.
+pub trait RoomStore: Send + Sync {
+    fn save(&self, snapshot: &str) -> io::Result<()>;
+
+    /// None when nothing was saved yet
+    fn load(&self) -> io::Result<Option<String>>;
+}
+
+/// Keeps the snapshot in a local file.
+///
+/// It includes the player tokens, so the file should be as private as the server itself.
+pub struct FileRoomStore {
+    path: PathBuf
+}
+
+impl FileRoomStore {
+    pub fn new(path: impl Into<PathBuf>) -> FileRoomStore {
+        FileRoomStore { path: path.into() }
+    }
+}
+
+impl RoomStore for FileRoomStore {
+    fn save(&self, snapshot: &str) -> io::Result<()> {
+        // Written next to it first, so that a crash halfway through never leaves a broken snapshot behind
+        let temporary_path = self.path.with_extension("tmp");
+        fs::write(&temporary_path, snapshot)?;
+        fs::rename(&temporary_path, &self.path)
+    }
+
+    fn load(&self) -> io::Result<Option<String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(snapshot) => Ok(Some(snapshot)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error)
+        }
+    }
+}
+
+// Instants only make sense within the same process, so they are saved as how long ago they were
+pub(crate) mod instant_age {
+    use std::time::{Duration, Instant};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(instant: &Instant, serializer: S) -> Result<S::Ok, S::Error> {
+        u64::try_from(instant.elapsed().as_millis()).unwrap_or(u64::MAX).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Instant, D::Error> {
+        let age = Duration::from_millis(u64::deserialize(deserializer)?);
+        Ok(Instant::now().checked_sub(age).unwrap_or_else(Instant::now))
+    }
+}
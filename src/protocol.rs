@@ -0,0 +1,332 @@
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestRoomCreate {
+    pub owner_name: String,
+    pub settings: Option<RoomSettings>
+}
+
+/// Any field left out takes its default value
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct RoomSettings {
+    pub round_total: u8,
+    pub hand_size: u8,
+    pub prompt_option_count: u8,
+    pub min_players: u8,
+    pub max_players: u8,
+    pub game_mode: GameMode,
+    pub score_to_win: u8, // Only for the points mode
+    pub tie_break: TieBreak,
+    pub phase_timers: PhaseTimers
+}
+
+/// How a game ends: after `round_total` rounds, or as soon as someone gets to `score_to_win` points
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    Rounds,
+    Points
+}
+
+/// What happens when the game ends with more than one player on top: they all win, or extra rounds are played until one is ahead
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    Shared,
+    SuddenDeath
+}
+
+/// Seconds that each phase can last before the server makes the missing choices at random, zero for no limit
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct PhaseTimers {
+    pub leader_options: u16,
+    pub lackey_options: u16,
+    pub leader_pick: u16,
+    pub round_winner: u16
+}
+
+/// Changes to the current settings, any field left out keeps its current value
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct RoomSettingsUpdate {
+    pub round_total: Option<u8>,
+    pub hand_size: Option<u8>,
+    pub prompt_option_count: Option<u8>,
+    pub min_players: Option<u8>,
+    pub max_players: Option<u8>,
+    pub game_mode: Option<GameMode>,
+    pub score_to_win: Option<u8>,
+    pub tie_break: Option<TieBreak>,
+    pub phase_timers: Option<PhaseTimersUpdate>
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct PhaseTimersUpdate {
+    pub leader_options: Option<u16>,
+    pub lackey_options: Option<u16>,
+    pub leader_pick: Option<u16>,
+    pub round_winner: Option<u16>
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseRoomCreate {
+    pub room_id: u32,
+    pub room_code: String,
+    pub player_id: u32,
+    pub player_token: String,
+    pub reconnect_token: String
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestRoomJoin {
+    pub player_name: String,
+    pub room_code: String
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseRoomJoin {
+    pub room_id: u32,
+    pub player_id: u32,
+    pub player_name: String, // Might not be the requested one, see DUPLICATE_PLAYER_NAMES
+    pub player_token: String,
+    pub reconnect_token: String
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RequestRoomRejoin {
+    pub room_code: String,
+    pub reconnect_token: String
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestRoomCheck {
+    pub room_id: u32,
+    pub player_id: u32
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ResponseRoomCheck {
+    pub players: Vec<ResponseRoomCheckPlayer>,
+    pub room_status: String,
+    pub owner_id: u32,
+    pub is_locked: bool,
+    pub leader_id: u32,
+    pub round_counter: u8,
+    pub round_total: u8,
+    pub settings: RoomSettings,
+    pub phase_seconds_left: Option<u16>,
+    pub prompt_text: Option<String>,
+    pub finishers: Option<Vec<ResponseRoomCheckFinisher>>,
+    pub game_winner_ids: Option<Vec<u32>>,
+    pub standings: Option<Vec<ResponseRoomCheckStanding>>,
+    pub tie_groups: Option<Vec<Vec<u32>>>,
+    pub rounds: Option<Vec<ResponseRoomCheckRound>>
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ResponseRoomCheckPlayer {
+    pub player_id: u32,
+    pub player_name: String,
+    pub score: u8,
+    pub is_finisher_ready: Option<bool>,
+    pub is_next_round_ready: Option<bool>,
+    pub last_check: u16,
+    pub is_connected: bool
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ResponseRoomCheckStanding {
+    pub player_id: u32,
+    pub player_name: String,
+    pub score: u8,
+    pub rank: u8, // Shared by tied players
+    pub rounds_won: u8
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ResponseRoomCheckRound {
+    pub round_counter: u8,
+    pub prompt_text: Option<String>,
+    pub winner_player_id: u32,
+    pub winner_player_name: String,
+    pub winner_finisher_text: String
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ResponseRoomCheckFinisher {
+    pub player_name: String,
+    pub finisher_text: String,
+    pub is_winner: bool
+}
+
+
+#[derive(Serialize, Debug)]
+pub struct ResponseRoomHistory {
+    pub rounds: Vec<ResponseRoomHistoryRound>
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseRoomHistoryRound {
+    pub game_counter: u8,
+    pub round_counter: u8,
+    pub leader_id: u32,
+    pub leader_name: String,
+    pub prompt_text: Option<String>,
+    pub finishers: Vec<ResponseRoomHistoryFinisher>,
+    pub winner_player_id: u32
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseRoomHistoryFinisher {
+    pub player_id: u32,
+    pub player_name: String,
+    pub finisher_text: String,
+    pub is_winner: bool
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestRoomLeave {
+    pub room_id: u32,
+    pub player_id: u32
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestRoomSettings {
+    pub room_id: u32,
+    pub player_id: u32,
+    pub settings: RoomSettingsUpdate
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestRoomKick {
+    pub room_id: u32,
+    pub player_id: u32,
+    pub target_player_id: u32,
+    #[serde(default)]
+    pub ban: bool
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestRoomTransfer {
+    pub room_id: u32,
+    pub player_id: u32,
+    pub target_player_id: u32
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestRoomLock {
+    pub room_id: u32,
+    pub player_id: u32,
+    pub is_locked: bool
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestGameStart {
+    #[serde(default)] // Can be omitted through WebSockets, once authenticated
+    pub room_id: u32,
+    #[serde(default)]
+    pub player_id: u32
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestGameForceAdvance {
+    #[serde(default)] // Can be omitted through WebSockets, once authenticated
+    pub room_id: u32,
+    #[serde(default)]
+    pub player_id: u32
+}
+
+
+/// The outcome of the Game* actions and of RoomLeave, RoomSettings, RoomKick, RoomTransfer and RoomLock,
+/// so that the client does not need another RoomCheck
+#[derive(Serialize, Debug)]
+pub struct ResponseGameAction {
+    pub room_status: String,
+    pub round_counter: u8,
+    pub round_total: u8,
+    pub state_changed: bool // Whether the action changed the room at all, false for GameOptions or marking ready twice
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestGameOptions {
+    #[serde(default)] // Can be omitted through WebSockets, once authenticated
+    pub room_id: u32,
+    #[serde(default)]
+    pub player_id: u32
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseGameOptions {
+    pub options: Vec<ResponseGameOptionsOption>,
+    #[serde(flatten)]
+    pub result: ResponseGameAction
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseGameOptionsOption {
+    pub option_id: u16,
+    pub option_text: String
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct RequestGamePick {
+    #[serde(default)] // Can be omitted through WebSockets, once authenticated
+    pub room_id: u32,
+    #[serde(default)]
+    pub player_id: u32,
+    pub option_id: u16
+}
+
+
+#[derive(Serialize, Debug)]
+pub struct ResponseError {
+    pub error: String,
+    pub message: String
+}
+
+
+/// Browsers cannot set headers on a WebSocket, so the token travels in the first message instead
+#[derive(Deserialize, Debug)]
+pub struct RequestSocketAuth {
+    pub room_id: u32,
+    pub player_id: u32,
+    pub player_token: String
+}
+
+/// Messages sent by WebSocket clients. The first one must be `auth`, the rest act on the authenticated room and player
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SocketRequest {
+    Auth(RequestSocketAuth),
+    GameStart(RequestGameStart),
+    GameForceAdvance(RequestGameForceAdvance),
+    GameOptions(RequestGameOptions),
+    GamePick(RequestGamePick)
+}
+
+/// Messages sent to WebSocket clients, either as a reply to a `SocketRequest` or pushed when their room changes
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SocketResponse {
+    Room(ResponseRoomCheck),
+    GameStart(ResponseGameAction),
+    GameForceAdvance(ResponseGameAction),
+    GameOptions(ResponseGameOptions),
+    GamePick(ResponseGameAction),
+    Error(ResponseError)
+}
@@ -0,0 +1,238 @@
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::Mutex;
+
+use tungstenite::{Error, Message, WebSocket};
+
+use crate::error::GameError;
+use crate::game::GameEngine;
+use crate::protocol::{ResponseRoomCheck, SocketRequest, SocketResponse};
+
+
+/// WebSocket clients, polled without blocking from a thread of their own, apart from the HTTP workers.
+///
+/// A client authenticates once with an `auth` message and from then on it can send the same
+/// actions as the HTTP endpoints, while also getting its room state pushed whenever it changes.
+pub struct RoomSockets {
+    clients: Vec<SocketClient>,
+    next_client_id: u64
+}
+
+struct SocketClient {
+    id: u64,
+    websocket: WebSocket<TcpStream>,
+    session: Option<(u32, u32)>, // RoomId, PlayerId
+    closed: bool
+}
+
+// What a client sent since the last poll, or what it gets back for it
+struct SocketExchange {
+    client_id: u64,
+    session: Option<(u32, u32)>,
+    texts: Vec<String>
+}
+
+impl Default for RoomSockets {
+    fn default() -> Self {
+        RoomSockets::new()
+    }
+}
+
+impl RoomSockets {
+    pub fn new() -> RoomSockets {
+        RoomSockets { clients: vec![], next_client_id: 0 }
+    }
+
+    pub fn add(&mut self, websocket: WebSocket<TcpStream>) {
+        if let Err(error) = websocket.get_ref().set_nonblocking(true) {
+            println!("WebSocket - Cant make the socket non-blocking: {}", error);
+            return;
+        }
+
+        self.next_client_id += 1;
+        self.clients.push(SocketClient {
+            id: self.next_client_id,
+            websocket,
+            session: None,
+            closed: false
+        });
+    }
+
+    pub fn has_subscribers(&self, room_id: u32) -> bool {
+        self.clients.iter().any(|client| client.room_id() == Some(room_id))
+    }
+
+    /// The room and player of every authenticated client
+    pub fn sessions(&self) -> Vec<(u32, u32)> {
+        self.clients.iter().filter_map(|client| client.session).collect()
+    }
+
+    /// Drops the clients of a player that is no longer in the room
+    pub fn close_player(&mut self, room_id: u32, player_id: u32) {
+        self.clients.retain(|client| client.session != Some((room_id, player_id)));
+    }
+
+    /// Drops the clients of a room that no longer exists
+    pub fn close_room(&mut self, room_id: u32) {
+        self.clients.retain(|client| client.room_id() != Some(room_id));
+    }
+
+    /// Handles every message the clients have sent so far, returning the rooms that might have changed.
+    ///
+    /// The sockets are only locked to read the messages and to send the responses, not while the game
+    /// actions run, so that publishing the state of other rooms does not wait for them.
+    pub fn poll(room_sockets: &Mutex<RoomSockets>, game_engine: &GameEngine) -> Vec<u32> {
+        let socket_requests = room_sockets.lock().unwrap().receive();
+
+        let mut changed_room_ids = vec![];
+        let socket_responses: Vec<SocketExchange> = socket_requests.into_iter().map(|socket_request| {
+            let mut session = socket_request.session;
+            let texts = socket_request.texts.iter().map(|text| {
+                let (socket_response, changed_room_id) = handle(game_engine, &mut session, text);
+                if let Some(room_id) = changed_room_id
+                    && !changed_room_ids.contains(&room_id) {
+                    changed_room_ids.push(room_id);
+                }
+                serde_json::to_string(&socket_response).unwrap()
+            }).collect();
+
+            SocketExchange { client_id: socket_request.client_id, session, texts }
+        }).collect();
+
+        room_sockets.lock().unwrap().reply(socket_responses);
+
+        changed_room_ids
+    }
+
+    fn receive(&mut self) -> Vec<SocketExchange> {
+        let mut socket_requests = vec![];
+
+        for client in self.clients.iter_mut() {
+            let mut texts = vec![];
+            loop {
+                match client.websocket.read() {
+                    Ok(Message::Text(text)) => texts.push(text),
+                    Ok(Message::Close(_)) => {
+                        client.closed = true;
+                        break;
+                    },
+                    // Pings are answered by tungstenite itself
+                    Ok(_) => {},
+                    Err(Error::Io(error)) if error.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => {
+                        client.closed = true;
+                        break;
+                    }
+                }
+            }
+
+            if !texts.is_empty() {
+                socket_requests.push(SocketExchange { client_id: client.id, session: client.session, texts });
+            }
+        }
+
+        self.clients.retain(|client| !client.closed);
+
+        socket_requests
+    }
+
+    // Clients closed in between, e.g. of a player kicked meanwhile, are not answered
+    fn reply(&mut self, socket_responses: Vec<SocketExchange>) {
+        for socket_response in socket_responses {
+            let Some(client) = self.clients.iter_mut().find(|client| client.id == socket_response.client_id) else {
+                continue;
+            };
+
+            client.session = socket_response.session;
+            for text in &socket_response.texts {
+                client.send(text);
+            }
+        }
+
+        self.clients.retain(|client| !client.closed);
+    }
+
+    pub fn publish(&mut self, room_id: u32, room_state: &ResponseRoomCheck) {
+        let serialized_response = serde_json::to_string(&SocketResponse::Room(room_state.clone())).unwrap();
+
+        for client in self.clients.iter_mut().filter(|client| client.room_id() == Some(room_id)) {
+            client.send(&serialized_response);
+        }
+
+        self.clients.retain(|client| !client.closed);
+    }
+}
+
+impl SocketClient {
+    fn room_id(&self) -> Option<u32> {
+        self.session.map(|(room_id, _)| room_id)
+    }
+
+    fn send(&mut self, serialized_response: &str) {
+        match self.websocket.send(Message::Text(serialized_response.to_string())) {
+            Ok(_) => {},
+            // Queued, it gets flushed on the next poll
+            Err(Error::Io(error)) if error.kind() == ErrorKind::WouldBlock => {},
+            Err(_) => self.closed = true
+        }
+    }
+}
+
+// Returns the response, and the room whose state changed if any
+fn handle(game_engine: &GameEngine, session: &mut Option<(u32, u32)>, text: &str) -> (SocketResponse, Option<u32>) {
+    let socket_request = match serde_json::from_str::<SocketRequest>(text) {
+        Ok(socket_request) => socket_request,
+        Err(_) => return (SocketResponse::Error(GameError::InvalidJson.to_response()), None)
+    };
+
+    if let SocketRequest::Auth(request_socket_auth) = socket_request {
+        let room_id = request_socket_auth.room_id;
+        let player_id = request_socket_auth.player_id;
+        let authenticated = game_engine.authenticate(room_id, player_id, &request_socket_auth.player_token)
+            .and_then(|_| game_engine.check(room_id, player_id));
+
+        return match authenticated {
+            Ok(response_room_check) => {
+                *session = Some((room_id, player_id));
+                (SocketResponse::Room(response_room_check), None)
+            },
+            Err(error) => (SocketResponse::Error(error.to_response()), None)
+        };
+    }
+
+    let (room_id, player_id) = match *session {
+        Some(session) => session,
+        None => return (SocketResponse::Error(GameError::NotAuthenticated.to_response()), None)
+    };
+
+    let (requested_room_id, requested_player_id) = match &socket_request {
+        SocketRequest::GameStart(request) => (request.room_id, request.player_id),
+        SocketRequest::GameForceAdvance(request) => (request.room_id, request.player_id),
+        SocketRequest::GameOptions(request) => (request.room_id, request.player_id),
+        SocketRequest::GamePick(request) => (request.room_id, request.player_id),
+        SocketRequest::Auth(..) => (0, 0)
+    };
+    // The ids can be omitted, but when sent they must match the authenticated ones
+    if (requested_room_id != 0 && requested_room_id != room_id) || (requested_player_id != 0 && requested_player_id != player_id) {
+        return (SocketResponse::Error(GameError::NotAuthenticated.to_response()), None);
+    }
+
+    let result = match socket_request {
+        SocketRequest::GameStart(..) => game_engine.start(room_id, player_id).map(SocketResponse::GameStart),
+        SocketRequest::GameForceAdvance(..) => game_engine.force_advance(room_id, player_id).map(SocketResponse::GameForceAdvance),
+        SocketRequest::GameOptions(..) => game_engine.options(room_id, player_id).map(SocketResponse::GameOptions),
+        SocketRequest::GamePick(request) => game_engine.pick(room_id, player_id, request.option_id).map(SocketResponse::GamePick),
+        SocketRequest::Auth(..) => Err(GameError::InvalidData)
+    };
+
+    match result {
+        Ok(socket_response) => {
+            let changed = matches!(socket_response, SocketResponse::GameStart(..) | SocketResponse::GameForceAdvance(..) | SocketResponse::GamePick(..));
+            (socket_response, changed.then_some(room_id))
+        },
+        Err(error) => {
+            println!("WebSocket - {}", error);
+            (SocketResponse::Error(error.to_response()), None)
+        }
+    }
+}
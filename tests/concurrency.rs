@@ -0,0 +1,62 @@
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+use se_pelo::game::GameEngine;
+use se_pelo::protocol::RoomSettings;
+
+
+const LACKEY_COUNT: usize = 64;
+
+fn new_game_engine() -> GameEngine {
+    let prompts = (0..10).map(|i| format!("Prompt {} ___", i)).collect();
+    let finishers = (0..(LACKEY_COUNT * 8 + 8)).map(|i| format!("Finisher {}", i)).collect();
+    GameEngine::new(prompts, finishers)
+}
+
+#[test]
+fn concurrent_lackey_picks_are_never_lost() {
+    for _ in 0..10 {
+        let game_engine = Arc::new(new_game_engine());
+
+        let settings = RoomSettings { max_players: LACKEY_COUNT as u8 + 1, ..RoomSettings::default() };
+        let response_room_create = game_engine.create_room("Owner", Some(settings)).unwrap();
+        let room_id = response_room_create.room_id;
+        let leader_id = response_room_create.player_id;
+
+        let lackey_ids: Vec<u32> = (0..LACKEY_COUNT)
+            .map(|i| game_engine.join_room(&response_room_create.room_code, &format!("Lackey {}", i)).unwrap().player_id)
+            .collect();
+
+        game_engine.start(room_id, leader_id).unwrap();
+        let prompt_options = game_engine.options(room_id, leader_id).unwrap();
+        game_engine.pick(room_id, leader_id, prompt_options.options[0].option_id).unwrap();
+
+        // Every lackey draws its hand and submits a finisher at the same time
+        let barrier = Arc::new(Barrier::new(LACKEY_COUNT));
+        let lackeys: Vec<thread::JoinHandle<()>> = lackey_ids.iter().map(|&lackey_id| {
+            let game_engine = Arc::clone(&game_engine);
+            let barrier = Arc::clone(&barrier);
+
+            thread::spawn(move || {
+                barrier.wait();
+                let finisher_options = game_engine.options(room_id, lackey_id).unwrap();
+                game_engine.pick(room_id, lackey_id, finisher_options.options[0].option_id).unwrap();
+            })
+        }).collect();
+
+        for lackey in lackeys {
+            lackey.join().unwrap();
+        }
+
+        let room_state = game_engine.room_state(room_id).unwrap();
+        assert_eq!(room_state.room_status, "LEADER_PICK");
+        assert!(room_state.players.iter().filter(|p| p.player_id != leader_id).all(|p| p.is_finisher_ready == Some(true)));
+
+        // No two lackeys were dealt the same finisher, so every submission shows up once
+        let finisher_options = game_engine.options(room_id, leader_id).unwrap();
+        let mut submitted_finisher_ids: Vec<u16> = finisher_options.options.iter().map(|o| o.option_id).collect();
+        submitted_finisher_ids.sort();
+        submitted_finisher_ids.dedup();
+        assert_eq!(submitted_finisher_ids.len(), LACKEY_COUNT);
+    }
+}